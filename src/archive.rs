@@ -0,0 +1,319 @@
+use crate::{decode, ScanError};
+use image::ImageError;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Separates the path of an archive from the path of a file inside it,
+/// e.g. `backup.zip!/DCIM/IMG_1.jpg`
+pub const SEPARATOR: &str = "!/";
+
+/// Largest file read from inside an archive.  Sizes come from the archive's
+/// own headers, so a broken or malicious one could claim any amount.
+const MAX_ENTRY: u64 = 512 * 1024 * 1024;
+
+/// Archive formats we can look inside
+enum Format {
+    Zip,
+    Tar,
+}
+
+/// The format of an archive, going by its extension
+fn format(path: &Path) -> Option<Format> {
+    match decode::extension(path).as_str() {
+        "zip" | "cbz" => Some(Format::Zip),
+        "tar" => Some(Format::Tar),
+        _ => None,
+    }
+}
+
+/// The format of an archive, or an error for anything else
+fn format_of(archive: &Path) -> io::Result<Format> {
+    format(archive).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("{} is not a zip or tar archive", archive.display()),
+        )
+    })
+}
+
+/// Whether the file is an archive we can look inside
+pub fn is_archive(path: &Path) -> bool {
+    format(path).is_some()
+}
+
+/// Whether the path points inside an archive
+pub fn is_virtual(path: &Path) -> bool {
+    split(path).is_some()
+}
+
+/// List the names of the images inside an archive, without reading them.
+/// Videos are left out, as ffmpeg can only read them from disk.
+pub fn list(archive: &Path) -> io::Result<Vec<String>> {
+    let names = match format_of(archive)? {
+        Format::Zip => list_zip(archive)?,
+        Format::Tar => list_tar(archive)?,
+    };
+    Ok(names
+        .into_iter()
+        .filter(|name| decode::is_supported(&join(archive, name), false))
+        .collect())
+}
+
+/// Read the files called `names` from an archive in a single pass, in the
+/// order they are stored, handing each to `f` as its virtual path, contents
+/// and size.  Stops early once `f` gives `false`.
+pub fn read_each<F>(archive: &Path, names: &[String], mut f: F) -> io::Result<()>
+where
+    F: FnMut(PathBuf, &mut dyn Read, u64) -> bool,
+{
+    let names: HashSet<&str> = names.iter().map(String::as_str).collect();
+    match format_of(archive)? {
+        Format::Zip => {
+            let mut zip = zip::ZipArchive::new(File::open(archive)?).map_err(to_io_error)?;
+            for i in 0..zip.len() {
+                let mut file = zip.by_index(i).map_err(to_io_error)?;
+                if names.contains(file.name()) {
+                    let (path, size) = (join(archive, file.name()), file.size());
+                    if !f(path, &mut file, size) {
+                        break;
+                    }
+                }
+            }
+        }
+        Format::Tar => {
+            let mut tar = tar::Archive::new(File::open(archive)?);
+            for entry in tar.entries()? {
+                let mut entry = entry?;
+                let name = entry.path()?.to_string_lossy().into_owned();
+                if names.contains(name.as_str()) {
+                    let size = entry.header().size()?;
+                    if !f(join(archive, &name), &mut entry, size) {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Read a file from inside an archive into memory.
+/// Archives are only ever opened for reading.
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    with_entry(path, |reader, size| {
+        read_entry(path, reader, size).map_err(|e| match e {
+            ScanError::Io { cause, .. } => cause,
+            e => io::Error::new(io::ErrorKind::InvalidData, e.reason()),
+        })
+    })
+}
+
+/// Read the file at the virtual `path` from `reader`, which its header says
+/// is `size` bytes long.  Files over `MAX_ENTRY` are refused.
+pub(crate) fn read_entry(
+    path: &Path,
+    reader: &mut dyn Read,
+    size: u64,
+) -> Result<Vec<u8>, ScanError> {
+    let too_large = || ScanError::Decode {
+        path: path.to_path_buf(),
+        cause: ImageError::FormatError(format!(
+            "Files inside archives may be at most {} MiB",
+            MAX_ENTRY / 1024 / 1024
+        )),
+    };
+    if size > MAX_ENTRY {
+        return Err(too_large());
+    }
+    let mut data = Vec::with_capacity(size as usize);
+    // The header may understate the size, so stop reading just past the limit
+    reader
+        .take(MAX_ENTRY + 1)
+        .read_to_end(&mut data)
+        .map_err(|cause| ScanError::Io {
+            path: path.to_path_buf(),
+            cause,
+        })?;
+    if data.len() as u64 > MAX_ENTRY {
+        return Err(too_large());
+    }
+    Ok(data)
+}
+
+/// Find a file inside an archive and hand it to `f` along with its size
+fn with_entry<T, F>(path: &Path, f: F) -> io::Result<T>
+where
+    F: FnOnce(&mut dyn Read, u64) -> io::Result<T>,
+{
+    let (archive, name) = split(path).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidInput, "Not a path inside an archive")
+    })?;
+    if let Format::Zip = format_of(&archive)? {
+        let mut zip = zip::ZipArchive::new(File::open(&archive)?).map_err(to_io_error)?;
+        let mut file = zip.by_name(&name).map_err(to_io_error)?;
+        let size = file.size();
+        return f(&mut file, size);
+    }
+    // Tar has no index, so walk through the headers until we find the entry
+    let mut tar = tar::Archive::new(File::open(&archive)?);
+    for entry in tar.entries_with_seek()? {
+        let mut entry = entry?;
+        if entry.path()?.to_string_lossy() == name {
+            let size = entry.header().size()?;
+            return f(&mut entry, size);
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} not found in {}", name, archive.display()),
+    ))
+}
+
+/// Split a virtual path into the archive and the name of the file inside it.
+/// Folders may have the separator in their names too, so the archive is the
+/// first part of the path that is an archive file.  An archive that is gone
+/// is still recognised by its extension.
+pub fn split(path: &Path) -> Option<(PathBuf, String)> {
+    let path = path.to_str()?;
+    let candidates: Vec<(PathBuf, &str)> = path
+        .match_indices(SEPARATOR)
+        .map(|(i, _)| (PathBuf::from(&path[..i]), &path[i + SEPARATOR.len()..]))
+        .filter(|(archive, _)| is_archive(archive))
+        .collect();
+    let (archive, name) = candidates
+        .iter()
+        .find(|(archive, _)| archive.is_file())
+        .or_else(|| candidates.first())?;
+    Some((archive.clone(), name.to_string()))
+}
+
+fn join(archive: &Path, name: &str) -> PathBuf {
+    PathBuf::from(format!("{}{}{}", archive.display(), SEPARATOR, name))
+}
+
+fn list_zip(archive: &Path) -> io::Result<Vec<String>> {
+    let mut zip = zip::ZipArchive::new(File::open(archive)?).map_err(to_io_error)?;
+    let mut names = Vec::with_capacity(zip.len());
+    for i in 0..zip.len() {
+        let file = zip.by_index(i).map_err(to_io_error)?;
+        if !file.name().ends_with('/') {
+            names.push(file.name().to_string());
+        }
+    }
+    Ok(names)
+}
+
+fn list_tar(archive: &Path) -> io::Result<Vec<String>> {
+    let mut tar = tar::Archive::new(File::open(archive)?);
+    let mut names = Vec::new();
+    // Seeking over the contents, as only the headers are needed
+    for entry in tar.entries_with_seek()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_file() {
+            names.push(entry.path()?.to_string_lossy().into_owned());
+        }
+    }
+    Ok(names)
+}
+
+fn to_io_error(e: zip::result::ZipError) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::Write;
+
+    #[test]
+    fn lists_and_reads_zip() {
+        let path = std::env::temp_dir().join("img_dedup_archive_test.zip");
+        let image = fs::read("test/rustA500.jpg").unwrap();
+        {
+            let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+            let options = zip::write::FileOptions::default();
+            zip.start_file("DCIM/IMG_1.jpg", options).unwrap();
+            zip.write_all(&image).unwrap();
+            zip.start_file("notes.txt", options).unwrap();
+            zip.write_all(b"not an image").unwrap();
+            zip.finish().unwrap();
+        }
+
+        let listed = list(&path).unwrap();
+        assert_eq!(listed, vec!["DCIM/IMG_1.jpg".to_string()]);
+        let virtual_path = join(&path, &listed[0]);
+        assert!(is_virtual(&virtual_path));
+        assert_eq!(read(&virtual_path).unwrap(), image);
+
+        let mut read = Vec::new();
+        read_each(&path, &listed, |entry, reader, size| {
+            let mut data = Vec::new();
+            reader.read_to_end(&mut data).unwrap();
+            read.push((entry, data.len() as u64, size));
+            true
+        })
+        .unwrap();
+        assert_eq!(
+            read,
+            vec![(virtual_path, image.len() as u64, image.len() as u64)]
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reads_tar_in_one_pass() {
+        let path =
+            std::env::temp_dir().join(format!("img_dedup_archive_{}.tar", std::process::id()));
+        let image = fs::read("test/rustA500.jpg").unwrap();
+        {
+            let mut tar = tar::Builder::new(File::create(&path).unwrap());
+            for name in &["a.jpg", "notes.txt", "b.png"] {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(image.len() as u64);
+                header.set_cksum();
+                tar.append_data(&mut header, name, &image[..]).unwrap();
+            }
+            tar.finish().unwrap();
+        }
+
+        let listed = list(&path).unwrap();
+        let mut read = Vec::new();
+        read_each(&path, &listed, |entry, _, size| {
+            read.push((entry, size));
+            true
+        })
+        .unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(listed, vec!["a.jpg".to_string(), "b.png".to_string()]);
+        let size = image.len() as u64;
+        assert_eq!(
+            read,
+            vec![(join(&path, "a.jpg"), size), (join(&path, "b.png"), size)]
+        );
+        assert!(!is_archive(Path::new("photos.rar")));
+    }
+
+    #[test]
+    fn folders_may_look_like_archives() {
+        let folder = std::env::temp_dir().join(format!("img_dedup_{}.zip!", std::process::id()));
+        fs::create_dir_all(&folder).unwrap();
+        let path = folder.join("photos.zip");
+        zip::ZipWriter::new(File::create(&path).unwrap())
+            .finish()
+            .unwrap();
+
+        let split = split(&join(&path, "a!/b.jpg"));
+        fs::remove_dir_all(&folder).unwrap();
+        assert_eq!(split, Some((path, "a!/b.jpg".to_string())));
+    }
+
+    #[test]
+    fn plain_paths_are_not_virtual() {
+        assert!(!is_virtual(Path::new("test/rustA500.jpg")));
+        assert!(!is_virtual(Path::new("photos/wow!/rustA500.jpg")));
+    }
+}
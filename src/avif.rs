@@ -0,0 +1,175 @@
+//! AVIF stills, decoded without any C libraries.  `avif-parse` takes the
+//! AV1 picture out of its HEIF container, and rav1d, a Rust port of dav1d,
+//! decodes it through the same API dav1d has.  Only the primary picture is
+//! read, and transparency is ignored.
+
+use image::{DynamicImage, ImageError, ImageResult, RgbImage};
+use rav1d::include::dav1d::data::Dav1dData;
+use rav1d::include::dav1d::dav1d::Dav1dSettings;
+use rav1d::include::dav1d::headers::{
+    DAV1D_PIXEL_LAYOUT_I400, DAV1D_PIXEL_LAYOUT_I420, DAV1D_PIXEL_LAYOUT_I422,
+};
+use rav1d::include::dav1d::picture::Dav1dPicture;
+use rav1d::src::lib::{
+    dav1d_close, dav1d_data_create, dav1d_default_settings, dav1d_get_picture, dav1d_open,
+    dav1d_picture_unref, dav1d_send_data,
+};
+use std::mem::MaybeUninit;
+use std::ptr::{self, NonNull};
+
+/// Matrix coefficients of BT.709, as the sequence header numbers them.
+/// Anything else is converted as BT.601.
+const MATRIX_BT709: u32 = 1;
+
+pub(crate) fn decode(data: &[u8]) -> ImageResult<DynamicImage> {
+    let avif = avif_parse::read_avif(&mut &data[..])
+        .map_err(|e| format_error(&format!("Could not read AVIF container: {:?}", e)))?;
+    let mut picture = Decoder::new()?.decode(&avif.primary_item[..])?;
+    let rgb = to_rgb(&picture);
+    unsafe { dav1d_picture_unref(Some(NonNull::from(&mut picture))) };
+    rgb.map(DynamicImage::ImageRgb8)
+}
+
+/// A dav1d context, closed when dropped
+struct Decoder(Option<rav1d::include::dav1d::dav1d::Dav1dContext>);
+
+impl Decoder {
+    fn new() -> ImageResult<Decoder> {
+        let mut settings = MaybeUninit::<Dav1dSettings>::uninit();
+        let mut context = None;
+        let result = unsafe {
+            dav1d_default_settings(NonNull::new_unchecked(settings.as_mut_ptr()));
+            let settings = settings.assume_init_mut();
+            // One picture in gives one picture out, with no threads kept around
+            settings.n_threads = 1;
+            settings.max_frame_delay = 1;
+            dav1d_open(
+                Some(NonNull::from(&mut context)),
+                Some(NonNull::from(settings)),
+            )
+        };
+        if result.0 < 0 || context.is_none() {
+            return Err(format_error("Could not start the AV1 decoder"));
+        }
+        Ok(Decoder(context))
+    }
+
+    /// Decode a single AV1 picture
+    fn decode(&mut self, bitstream: &[u8]) -> ImageResult<Dav1dPicture> {
+        let mut data = Dav1dData::default();
+        let mut picture = Dav1dPicture::default();
+        unsafe {
+            let buffer = dav1d_data_create(Some(NonNull::from(&mut data)), bitstream.len());
+            if buffer.is_null() {
+                return Err(format_error("Could not allocate AV1 data"));
+            }
+            ptr::copy_nonoverlapping(bitstream.as_ptr(), buffer, bitstream.len());
+            if dav1d_send_data(self.0.clone(), Some(NonNull::from(&mut data))).0 < 0 {
+                return Err(format_error("Invalid AV1 data"));
+            }
+            if dav1d_get_picture(self.0.clone(), Some(NonNull::from(&mut picture))).0 < 0 {
+                return Err(format_error("Could not decode the AV1 picture"));
+            }
+        }
+        Ok(picture)
+    }
+}
+
+impl Drop for Decoder {
+    fn drop(&mut self) {
+        unsafe { dav1d_close(Some(NonNull::from(&mut self.0))) };
+    }
+}
+
+/// Convert a decoded picture from YUV at 8 to 12 bits to 8 bit RGB
+fn to_rgb(picture: &Dav1dPicture) -> ImageResult<RgbImage> {
+    let (width, height) = (picture.p.w as usize, picture.p.h as usize);
+    let layout = picture.p.layout;
+    let high_depth = picture.p.bpc > 8;
+    let shift = picture.p.bpc as u32 - 8;
+    let (full_range, bt709) = match picture.seq_hdr {
+        Some(header) => {
+            let header = unsafe { header.as_ref() };
+            (header.color_range != 0, header.mtrx as u32 == MATRIX_BT709)
+        }
+        None => (false, false),
+    };
+    let (sub_x, sub_y) = match layout {
+        l if l == DAV1D_PIXEL_LAYOUT_I420 => (1, 1),
+        l if l == DAV1D_PIXEL_LAYOUT_I422 => (1, 0),
+        _ => (0, 0),
+    };
+    let planes = [picture.data[0], picture.data[1], picture.data[2]];
+    let luma = planes[0].ok_or_else(|| format_error("AV1 picture has no pixels"))?;
+    // Read one sample, scaled down to 8 bits
+    let sample = |plane: NonNull<_>, stride: isize, x: usize, y: usize| -> i32 {
+        let row = unsafe { (plane.as_ptr() as *const u8).offset(stride * y as isize) };
+        if high_depth {
+            let value = unsafe { *(row as *const u16).add(x) };
+            i32::from(value >> shift)
+        } else {
+            i32::from(unsafe { *row.add(x) })
+        }
+    };
+
+    let mut pixels = Vec::with_capacity(width * height * 3);
+    for y in 0..height {
+        for x in 0..width {
+            let luma = sample(luma, picture.stride[0], x, y);
+            let (cb, cr) = match (layout == DAV1D_PIXEL_LAYOUT_I400, planes[1], planes[2]) {
+                (false, Some(u), Some(v)) => {
+                    let (cx, cy) = (x >> sub_x, y >> sub_y);
+                    (
+                        sample(u, picture.stride[1], cx, cy) - 128,
+                        sample(v, picture.stride[1], cx, cy) - 128,
+                    )
+                }
+                _ => (0, 0),
+            };
+            pixels.extend_from_slice(&yuv_to_rgb(luma, cb, cr, full_range, bt709));
+        }
+    }
+    RgbImage::from_raw(width as u32, height as u32, pixels).ok_or(ImageError::DimensionError)
+}
+
+fn yuv_to_rgb(luma: i32, cb: i32, cr: i32, full_range: bool, bt709: bool) -> [u8; 3] {
+    let (luma, cb, cr) = if full_range {
+        (f64::from(luma), f64::from(cb), f64::from(cr))
+    } else {
+        (
+            f64::from(luma - 16) * 255.0 / 219.0,
+            f64::from(cb) * 255.0 / 224.0,
+            f64::from(cr) * 255.0 / 224.0,
+        )
+    };
+    let (kr, kb) = if bt709 {
+        (0.2126, 0.0722)
+    } else {
+        (0.299, 0.114)
+    };
+    let kg = 1.0 - kr - kb;
+    let r = luma + 2.0 * (1.0 - kr) * cr;
+    let b = luma + 2.0 * (1.0 - kb) * cb;
+    let g = (luma - kr * r - kb * b) / kg;
+    let clamp = |v: f64| v.round().max(0.0).min(255.0) as u8;
+    [clamp(r), clamp(g), clamp(b)]
+}
+
+fn format_error(reason: &str) -> ImageError {
+    ImageError::FormatError(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn converts_both_ranges() {
+        assert_eq!(yuv_to_rgb(128, 0, 0, true, false), [128, 128, 128]);
+        assert_eq!(yuv_to_rgb(235, 0, 0, false, true), [255, 255, 255]);
+        assert_eq!(yuv_to_rgb(16, 0, 0, false, false), [0, 0, 0]);
+        // Strong red difference with no blue difference is red
+        let [r, g, b] = yuv_to_rgb(76, -43, 127, true, false);
+        assert!(r > 250 && g < 5 && b < 5, "{:?}", (r, g, b));
+    }
+}
@@ -0,0 +1,103 @@
+use failure::Error;
+use img_dedup::{
+    self as scanner, Config, IgnoreList, Phase, Progress, Review, SimilarPair, StatusMsg,
+};
+use log::warn;
+use std::collections::BinaryHeap;
+use std::io::{self, Write};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Width of the progress bar in characters
+const BAR_WIDTH: usize = 30;
+
+/// Redrawing on every image would flood slow terminals
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Scan without a window, drawing a progress bar on stderr
+/// and printing matches on stdout, most similar first.
+/// When carrying on with a session, only the pairs not yet decided on are printed.
+pub fn run(config: &Config) -> Result<(), Error> {
+    let review = match &config.session {
+        Some(path) if path.exists() => Review::open(path)?,
+        session => {
+            let review = Review::new(scan(config)?);
+            if let Some(path) = session {
+                review.save(path)?;
+            }
+            review
+        }
+    };
+
+    let undecided = (0..review.len())
+        .filter_map(|i| review.get(i))
+        .filter(|(_, decision)| decision.is_none());
+    for (pair, _) in undecided {
+        println!(
+            "{}\t{}\t{}\t{}",
+            pair.similarity,
+            pair.classification,
+            pair.left.path.display(),
+            pair.right.path.display()
+        );
+    }
+    Ok(())
+}
+
+fn scan(config: &Config) -> Result<BinaryHeap<SimilarPair>, Error> {
+    let (sender, receiver) = channel::<StatusMsg>();
+    let progress = thread::spawn(move || draw_progress(receiver));
+    // A broken ignore list should not stop the scan, only bring back those pairs
+    let ignored = IgnoreList::load().unwrap_or_else(|e| {
+        warn!("{}", e);
+        IgnoreList::default()
+    });
+    let files = scanner::scan(config, &ignored, sender);
+    progress.join().unwrap();
+    Ok(files?)
+}
+
+/// Redraw the progress bar until the scanner hangs up
+fn draw_progress(receiver: Receiver<StatusMsg>) {
+    let mut progress = Progress::new();
+    let mut last_draw = Instant::now();
+    for msg in receiver {
+        let phase_changed = matches!(msg, StatusMsg::Phase(_));
+        progress.update(msg);
+        if phase_changed || last_draw.elapsed() >= REDRAW_INTERVAL {
+            draw(&progress);
+            last_draw = Instant::now();
+        }
+    }
+    eprintln!();
+    for (path, reason) in &progress.failed {
+        eprintln!("Could not read {}: {}", path.display(), reason);
+    }
+}
+
+fn draw(progress: &Progress) {
+    let mut line = String::new();
+    let fraction = match progress.phase {
+        Phase::Hashing => Some(progress.fraction()),
+        Phase::Comparing => Some(progress.comparison_fraction()),
+        _ => None,
+    };
+    if let Some(fraction) = fraction {
+        let filled = (fraction * BAR_WIDTH as f64) as usize;
+        line.push_str(&format!(
+            "[{}{}] ",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled)
+        ));
+    }
+    line.push_str(&progress.to_string());
+    if progress.phase <= Phase::Hashing {
+        if let Some(name) = progress.current.as_ref().and_then(|p| p.file_name()) {
+            line.push_str(&format!(" ({})", name.to_string_lossy()));
+        }
+    }
+    // Return to the start of the line and clear what was there
+    eprint!("\r{}\x1b[K", line);
+    io::stderr().flush().ok();
+}
@@ -0,0 +1,76 @@
+use image::{DynamicImage, FilterType};
+use serde::{Deserialize, Serialize};
+
+/// Width and height of the thumbnail that the chroma signature is taken from
+const GRID_SIZE: u32 = 4;
+
+/// A Cb and a Cr value for each cell of the grid
+const BINS: usize = (GRID_SIZE * GRID_SIZE * 2) as usize;
+
+/// A coarse map of the chroma (Cb and Cr) of an image.
+///
+/// Every `HashType` works on luma, so a photo and its grayscale or sepia
+/// conversion hash the same.  Comparing these signatures tells them apart.
+#[derive(
+    Clone, Copy, Debug, Default, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize,
+)]
+pub struct ColorSignature([u8; BINS]);
+
+impl ColorSignature {
+    /// Build the signature from a decoded image
+    pub fn new(image: &DynamicImage) -> ColorSignature {
+        let thumbnail = image
+            .resize_exact(GRID_SIZE, GRID_SIZE, FilterType::Triangle)
+            .to_rgb();
+        let mut bins = [0; BINS];
+        for (bin, p) in bins.chunks_mut(2).zip(thumbnail.pixels()) {
+            let (r, g, b) = (
+                f32::from(p.data[0]),
+                f32::from(p.data[1]),
+                f32::from(p.data[2]),
+            );
+            bin[0] = to_channel(128.0 - 0.168_736 * r - 0.331_264 * g + 0.5 * b);
+            bin[1] = to_channel(128.0 + 0.5 * r - 0.418_688 * g - 0.081_312 * b);
+        }
+        ColorSignature(bins)
+    }
+
+    /// Mean absolute chroma difference between two signatures, from 0 to 255
+    pub fn distance(&self, other: &ColorSignature) -> u32 {
+        let total: u32 = self
+            .0
+            .iter()
+            .zip(other.0.iter())
+            .map(|(&a, &b)| (i32::from(a) - i32::from(b)).abs() as u32)
+            .sum();
+        total / BINS as u32
+    }
+}
+
+fn to_channel(value: f32) -> u8 {
+    value.round().max(0.0).min(255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgb};
+
+    fn solid(r: u8, g: u8, b: u8) -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_pixel(8, 8, Rgb { data: [r, g, b] }))
+    }
+
+    #[test]
+    fn grayscale_differs_from_color() {
+        let color = ColorSignature::new(&solid(200, 40, 40));
+        let gray = ColorSignature::new(&DynamicImage::ImageLuma8(solid(200, 40, 40).to_luma()));
+        assert!(color.distance(&gray) > 30);
+    }
+
+    #[test]
+    fn identical_images_have_no_distance() {
+        let a = ColorSignature::new(&solid(10, 120, 230));
+        let b = ColorSignature::new(&solid(10, 120, 230));
+        assert_eq!(a.distance(&b), 0);
+    }
+}
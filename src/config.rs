@@ -1,9 +1,11 @@
 use crate::hash_type::HashType;
+use crate::{profile, Preset, ScanError};
 use std::path::PathBuf;
 use structopt::StructOpt;
 
 const DEFAULT_DIR: &str = ".";
 const DEFAULT_HASH_LENGTH: u32 = 16;
+const DEFAULT_MEMORY_BUDGET: u64 = 1024;
 
 /// Configuration of the scanner
 #[derive(Clone, Debug, Eq, PartialEq, StructOpt)]
@@ -19,7 +21,7 @@ pub struct Config {
     pub directory: PathBuf,
     /// The hashing method to use
     #[structopt(
-        parse(from_str),
+        parse(try_from_str),
         short = "h",
         long = "hash_type",
         default_value = "Gradient"
@@ -28,12 +30,128 @@ pub struct Config {
     /// The square root of the length of the hash
     #[structopt(short = "l", long = "hash_length", default_value = "16")]
     pub hash_size: u32,
-    /// The square root of the length of the hash
+    /// Pick the method and hash length by name: fast, balanced or precise.
+    /// --hash_type and --hash_length still win when given.
+    #[structopt(long = "preset", parse(try_from_str))]
+    pub preset: Option<Preset>,
+    /// Threads used for hashing, or 0 for one per CPU
+    #[structopt(long = "threads", default_value = "0")]
+    pub threads: usize,
+    /// Files read from disk at once, or 0 for one per hashing thread
+    #[structopt(long = "io_threads", default_value = "0")]
+    pub io_threads: usize,
+    /// Read files in the order they are likely laid out on disk.
+    /// Best combined with a single I/O thread on spinning disks.
+    #[structopt(long = "ordered_reads")]
+    pub ordered_reads: bool,
+    /// Roughly how many MiB images may take up at once, both as read from
+    /// disk and decoded
+    #[structopt(long = "memory_budget", default_value = "1024")]
+    pub memory_budget: u64,
+    /// Also deduplicate videos by their keyframes (requires ffmpeg)
+    #[structopt(long = "video")]
+    pub video: bool,
+    /// Also look for images inside zip and tar archives (read-only)
+    #[structopt(long = "archives")]
+    pub archives: bool,
+    /// Settings profile to load from the config files
+    #[structopt(short = "p", long = "profile")]
+    pub profile: Option<String>,
+    /// Review session to carry on with instead of scanning.  When the file
+    /// does not exist yet, the scan results are saved in it.
+    #[structopt(long = "session", parse(from_os_str))]
+    pub session: Option<PathBuf>,
+    /// Save thumbnails to the cache shared with file managers while
+    /// hashing.  The window always does, as browsing is slower without them.
+    #[structopt(long = "thumbnails")]
+    pub thumbnails: bool,
+    /// Print matches to the terminal instead of opening a window
+    #[structopt(long = "no_gui")]
+    pub no_gui: bool,
+    /// How much to log: only errors by default, and warnings, info, debug
+    /// and trace messages as -v is repeated
     #[structopt(short = "v", parse(from_occurrences))]
     pub verbosity: u8,
 }
 
 impl Config {
+    /// Smallest hash length accepted, for 16 bit hashes
+    pub const MIN_HASH_SIZE: u32 = 4;
+    /// Largest hash length accepted.  Hashes grow with its square,
+    /// and so does the memory and time it takes to compare them.
+    pub const MAX_HASH_SIZE: u32 = 64;
+    /// Smallest memory budget accepted, in MiB, which a single photo may
+    /// well take up by itself
+    pub const MIN_MEMORY_BUDGET: u64 = 16;
+    /// Largest memory budget accepted, in MiB.  Anything more is surely a
+    /// typo, and would overflow once counted in bytes.
+    pub const MAX_MEMORY_BUDGET: u64 = 1024 * 1024;
+
+    /// Parse the command line, then fill in anything it leaves out from the
+    /// config files and the profile picked
+    pub fn from_args_and_files() -> Result<Config, ScanError> {
+        Config::load(None)
+    }
+
+    /// The same as `from_args_and_files`, but for scanning `directory`
+    /// instead, whose config file may pick other settings
+    pub fn for_directory(directory: PathBuf) -> Result<Config, ScanError> {
+        Config::load(Some(directory))
+    }
+
+    fn load(directory: Option<PathBuf>) -> Result<Config, ScanError> {
+        let matches = Config::clap().get_matches();
+        let given = |arg: &str| matches.occurrences_of(arg) > 0;
+        let mut config = Config::from_clap(&matches);
+        if let Some(directory) = directory {
+            config.directory = directory;
+        }
+        profile::load(&mut config, &given)?;
+        if let Some(preset) = config.preset {
+            if !given("method") {
+                config.method = preset.method();
+            }
+            if !given("hash_size") {
+                config.hash_size = preset.hash_size();
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check that the settings can be scanned with
+    pub fn validate(&self) -> Result<(), ScanError> {
+        if self.hash_size < Config::MIN_HASH_SIZE || self.hash_size > Config::MAX_HASH_SIZE {
+            return Err(ScanError::InvalidConfig {
+                reason: format!(
+                    "The hash length must be between {} and {}, not {}",
+                    Config::MIN_HASH_SIZE,
+                    Config::MAX_HASH_SIZE,
+                    self.hash_size
+                ),
+            });
+        }
+        if self.memory_budget < Config::MIN_MEMORY_BUDGET
+            || self.memory_budget > Config::MAX_MEMORY_BUDGET
+        {
+            return Err(ScanError::InvalidConfig {
+                reason: format!(
+                    "The memory budget must be between {} and {} MiB, not {}",
+                    Config::MIN_MEMORY_BUDGET,
+                    Config::MAX_MEMORY_BUDGET,
+                    self.memory_budget
+                ),
+            });
+        }
+        Ok(())
+    }
+
+    /// Save the current settings as a named profile in the user's config
+    /// file, returning where it was saved
+    pub fn save_profile(&self, name: &str) -> Result<PathBuf, ScanError> {
+        profile::save(self, name)
+    }
+
     /// Set the directory on the Config item
     pub fn set_directory(&mut self, dir: &str) {
         self.directory = PathBuf::from(dir);
@@ -51,7 +169,35 @@ impl Default for Config {
             directory: PathBuf::from(DEFAULT_DIR),
             method: HashType::default(),
             hash_size: DEFAULT_HASH_LENGTH,
+            preset: None,
+            threads: 0,
+            io_threads: 0,
+            ordered_reads: false,
+            memory_budget: DEFAULT_MEMORY_BUDGET,
+            video: false,
+            archives: false,
+            profile: None,
+            session: None,
+            thumbnails: false,
+            no_gui: false,
             verbosity: 0,
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_memory_budgets_out_of_range() {
+        let mut config = Config::default();
+        config.validate().unwrap();
+        config.memory_budget = 0;
+        assert!(config.validate().is_err());
+        config.memory_budget = u64::max_value();
+        assert!(config.validate().is_err());
+        config.memory_budget = Config::MAX_MEMORY_BUDGET;
+        config.validate().unwrap();
+    }
+}
@@ -0,0 +1,599 @@
+#[cfg(feature = "avif")]
+use crate::avif;
+use crate::{archive, video, webp, Digest};
+use gif::SetParameter;
+use image::{
+    DynamicImage, GenericImage, GrayImage, ImageDecoder, ImageError, ImageFormat, ImageResult,
+    RgbImage, Rgba, RgbaImage,
+};
+use jpeg_decoder::PixelFormat;
+use std::fs;
+use std::io::{self, Cursor, Read};
+use std::path::Path;
+
+/// Taken from the image crate's list of valid images
+const VALID_IMAGES: [&str; 15] = [
+    "jpg", "jpeg", "png", "gif", "webp", "tif", "tiff", "tga", "bmp", "ico", "hdr", "pbm", "pam",
+    "pgm", "ppm",
+];
+
+/// Camera RAW formats that carry an embedded JPEG preview
+#[cfg(feature = "raw")]
+const RAW_IMAGES: [&str; 11] = [
+    "cr2", "cr3", "nef", "nrw", "arw", "dng", "orf", "rw2", "raf", "pef", "srw",
+];
+
+/// HEVC images in the HEIF container, as iPhones take, decoded by libheif
+#[cfg(feature = "heif")]
+const HEIF_IMAGES: [&str; 2] = ["heic", "heif"];
+
+/// AV1 images in the HEIF container, decoded in pure Rust
+#[cfg(feature = "avif")]
+const AVIF_IMAGES: [&str; 2] = ["avif", "avifs"];
+
+/// Hashing shrinks images to a few dozen pixels across, so decoding JPEGs at
+/// any more than this is wasted memory
+const THUMBNAIL_SIZE: u16 = 256;
+
+/// Decoded bytes per compressed byte, for RAW and HEIF files whose size
+/// is not read before decoding them
+const FALLBACK_EXPANSION: u64 = 4;
+
+/// Assume video frames are 1080p, as ffmpeg is only asked for their size later
+const VIDEO_FRAME_PIXELS: u64 = 1920 * 1080;
+
+/// Only try this many embedded JPEG streams before giving up on a RAW file
+#[cfg(feature = "raw")]
+const MAX_PREVIEW_CANDIDATES: usize = 8;
+
+/// Whether the file extension is one we know how to decode.
+/// Videos are only included when asked for.
+pub fn is_supported(path: &Path, include_video: bool) -> bool {
+    let extension = extension(path);
+    let extension = extension.as_str();
+    if VALID_IMAGES.contains(&extension) || (include_video && video::is_video(&extension)) {
+        return true;
+    }
+    #[cfg(feature = "raw")]
+    {
+        if RAW_IMAGES.contains(&extension) {
+            return true;
+        }
+    }
+    #[cfg(feature = "heif")]
+    {
+        if HEIF_IMAGES.contains(&extension) {
+            return true;
+        }
+    }
+    #[cfg(feature = "avif")]
+    {
+        if AVIF_IMAGES.contains(&extension) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Open an image, dispatching to the right decoder for its format.
+/// Videos are represented by a keyframe from their middle.
+pub fn open(path: &Path) -> ImageResult<DynamicImage> {
+    let extension = extension(path);
+    if video::is_video(&extension) {
+        return video::keyframes(path, 1).map(|mut frames| frames.remove(0));
+    }
+    let data = read(path).map_err(ImageError::IoError)?;
+    decode_bytes(&extension, &data)
+}
+
+/// Open an image to show it, as a series of frames at full size.
+///
+/// Animated GIFs and WebPs yield up to `samples` frames spread evenly over
+/// their whole length.  Everything else yields the single frame `open` gives.
+pub fn open_frames(path: &Path, samples: usize) -> ImageResult<Vec<DynamicImage>> {
+    match extension(path).as_str() {
+        "gif" | "webp" => {
+            let encoded = read_encoded(path).map_err(ImageError::IoError)?;
+            encoded.decode_frames(path, samples).map(|d| d.frames)
+        }
+        _ => open(path).map(|image| vec![image]),
+    }
+}
+
+/// An image file that has been read into memory but not decoded yet.
+///
+/// Reading and decoding are kept apart so that disk access can be limited
+/// separately from the CPU-heavy decoding and hashing.
+pub struct Encoded {
+    extension: String,
+    data: Vec<u8>,
+    size: u64,
+    digest: Digest,
+}
+
+/// The frames of an image to hash, along with its real dimensions
+pub struct Decoded {
+    /// Frames to hash, which may be shrunk.  Stills have just one.
+    pub frames: Vec<DynamicImage>,
+    /// Width and height of the original image
+    pub dimensions: (u32, u32),
+}
+
+impl Decoded {
+    fn new(frames: Vec<DynamicImage>) -> Decoded {
+        let dimensions = frames.first().map_or((0, 0), GenericImage::dimensions);
+        Decoded { frames, dimensions }
+    }
+}
+
+/// Read an image file, which may be inside an archive, into memory.
+/// Videos are left on disk for ffmpeg to read, and only their ends are
+/// read for their digest.
+pub fn read_encoded(path: &Path) -> io::Result<Encoded> {
+    let extension = extension(path);
+    if !video::is_video(&extension) {
+        return Ok(Encoded::new(path, read(path)?));
+    }
+    Ok(Encoded {
+        extension,
+        data: Vec::new(),
+        size: fs::metadata(path)?.len(),
+        digest: Digest::sample(&mut fs::File::open(path)?)?,
+    })
+}
+
+/// How many bytes `read_encoded` keeps in memory for a file on disk
+pub(crate) fn encoded_size(path: &Path) -> u64 {
+    if video::is_video(&extension(path)) {
+        return 0;
+    }
+    fs::metadata(path).map_or(0, |m| m.len())
+}
+
+impl Encoded {
+    /// An image already read into memory from `path`
+    pub fn new(path: &Path, data: Vec<u8>) -> Encoded {
+        Encoded {
+            extension: extension(path),
+            size: data.len() as u64,
+            digest: Digest::of(&data),
+            data,
+        }
+    }
+
+    /// Size of the file in bytes, including videos left on disk
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Digest of the contents of the file
+    pub fn digest(&self) -> Digest {
+        self.digest
+    }
+
+    /// Decode the image as a series of frames for hashing.
+    ///
+    /// Animated GIFs, WebPs and videos yield up to `samples` frames spread
+    /// evenly over their whole length.  Everything else yields a single frame.
+    /// JPEGs are shrunk while decoding, as hashing only needs a thumbnail.
+    pub fn decode_frames(self, path: &Path, samples: usize) -> ImageResult<Decoded> {
+        match self.extension.as_str() {
+            "gif" => gif_frames(Cursor::new(self.data), samples).map(Decoded::new),
+            "webp" => webp::frames(&self.data, samples).map(Decoded::new),
+            "jpg" | "jpeg" => jpeg_thumbnail(Cursor::new(self.data)),
+            e if video::is_video(e) => video::keyframes(path, samples).map(Decoded::new),
+            e => decode_bytes(e, &self.data).map(|i| Decoded::new(vec![i])),
+        }
+    }
+
+    /// Guess how many bytes `decode_frames` will need
+    pub fn estimated_memory(&self, samples: usize) -> u64 {
+        let data = Cursor::new(&self.data);
+        let size = match self.extension.as_str() {
+            "jpg" | "jpeg" => {
+                let mut decoder = jpeg_decoder::Decoder::new(data);
+                decoder
+                    .scale(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+                    .ok()
+                    .map(|(width, height)| (u64::from(width) * u64::from(height), 1))
+            }
+            // The canvas plus up to twice the sampled frames are kept
+            "gif" => gif::Decoder::new(data).read_info().ok().map(|reader| {
+                let pixels = u64::from(reader.width()) * u64::from(reader.height());
+                (pixels, samples as u64 * 2 + 1)
+            }),
+            e if video::is_video(e) => Some((VIDEO_FRAME_PIXELS, samples as u64)),
+            "webp" if webp::is_animated(&self.data) => {
+                webp::dimensions(&self.data).map(|(width, height)| {
+                    (u64::from(width) * u64::from(height), samples as u64 * 2 + 1)
+                })
+            }
+            e => header_dimensions(e, &self.data)
+                .map(|(width, height)| (u64::from(width) * u64::from(height), 1)),
+        };
+        match size {
+            Some((pixels, frames)) => pixels * frames * 4,
+            None => self.data.len() as u64 * FALLBACK_EXPANSION,
+        }
+    }
+}
+
+/// The digest `read_encoded` gives a file, without holding it in memory
+pub(crate) fn digest(path: &Path) -> io::Result<Digest> {
+    if archive::is_virtual(path) {
+        Ok(Digest::of(&archive::read(path)?))
+    } else if video::is_video(&extension(path)) {
+        Digest::sample(&mut fs::File::open(path)?)
+    } else {
+        Digest::read(&mut fs::File::open(path)?)
+    }
+}
+
+/// Read the contents of a file, which may be inside an archive
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    if archive::is_virtual(path) {
+        archive::read(path)
+    } else {
+        fs::read(path)
+    }
+}
+
+/// Lowercase extension of a path, or an empty string
+pub(crate) fn extension(path: &Path) -> String {
+    path.extension()
+        .and_then(|s| s.to_str())
+        .map_or("".to_string(), |s| s.to_ascii_lowercase())
+}
+
+/// The `image` crate's format for an extension, as `image::open` would pick
+fn image_format(extension: &str) -> Option<ImageFormat> {
+    Some(match extension {
+        "jpg" | "jpeg" => ImageFormat::JPEG,
+        "png" => ImageFormat::PNG,
+        "gif" => ImageFormat::GIF,
+        "webp" => ImageFormat::WEBP,
+        "tif" | "tiff" => ImageFormat::TIFF,
+        "tga" => ImageFormat::TGA,
+        "bmp" => ImageFormat::BMP,
+        "ico" => ImageFormat::ICO,
+        "hdr" => ImageFormat::HDR,
+        "pbm" | "pam" | "pgm" | "ppm" => ImageFormat::PNM,
+        _ => return None,
+    })
+}
+
+/// Width and height read from the header of an image `decode_bytes`
+/// passes on to the `image` crate, without decoding it
+fn header_dimensions(extension: &str, data: &[u8]) -> Option<(u32, u32)> {
+    use image::{bmp::BMPDecoder, ico::ICODecoder, png::PNGDecoder, pnm::PNMDecoder};
+    use image::{tga::TGADecoder, tiff::TIFFDecoder};
+
+    let data = Cursor::new(data);
+    let dimensions = match image_format(extension)? {
+        ImageFormat::PNG => PNGDecoder::new(data).dimensions(),
+        // The image crate decodes the whole picture to find its size
+        ImageFormat::WEBP => return webp::dimensions(data.get_ref()),
+        ImageFormat::BMP => BMPDecoder::new(data).dimensions(),
+        ImageFormat::TGA => TGADecoder::new(data).dimensions(),
+        ImageFormat::TIFF => TIFFDecoder::new(data).and_then(|mut d| d.dimensions()),
+        ImageFormat::PNM => PNMDecoder::new(data).and_then(|mut d| d.dimensions()),
+        ImageFormat::ICO => ICODecoder::new(data).and_then(|mut d| d.dimensions()),
+        _ => return None,
+    };
+    dimensions.ok()
+}
+
+fn decode_bytes(extension: &str, data: &[u8]) -> ImageResult<DynamicImage> {
+    #[cfg(feature = "raw")]
+    {
+        if RAW_IMAGES.contains(&extension) {
+            return largest_embedded_jpeg(data)
+                .ok_or_else(|| ImageError::FormatError("No JPEG preview found".to_string()));
+        }
+    }
+    #[cfg(feature = "heif")]
+    {
+        if HEIF_IMAGES.contains(&extension) {
+            return decode_heif(data);
+        }
+    }
+    #[cfg(feature = "avif")]
+    {
+        if AVIF_IMAGES.contains(&extension) {
+            return avif::decode(data);
+        }
+    }
+    if extension == "webp" {
+        return webp::decode(data);
+    }
+    match image_format(extension) {
+        Some(format) => image::load_from_memory_with_format(data, format),
+        None => image::load_from_memory(data),
+    }
+}
+
+fn gif_frames<R: Read>(data: R, samples: usize) -> ImageResult<Vec<DynamicImage>> {
+    let to_image_error = |e: gif::DecodingError| ImageError::FormatError(e.to_string());
+    let mut decoder = gif::Decoder::new(data);
+    decoder.set(gif::ColorOutput::RGBA);
+    let mut reader = decoder.read_info().map_err(to_image_error)?;
+
+    let mut canvas = RgbaImage::new(u32::from(reader.width()), u32::from(reader.height()));
+    let mut sampler = Sampler::new(samples);
+    while let Some(frame) = reader.read_next_frame().map_err(to_image_error)? {
+        let previous = match frame.dispose {
+            gif::DisposalMethod::Previous => Some(canvas.clone()),
+            _ => None,
+        };
+        draw_frame(&mut canvas, frame);
+        sampler.offer(&canvas);
+        match frame.dispose {
+            gif::DisposalMethod::Background => clear_frame(&mut canvas, frame),
+            gif::DisposalMethod::Previous => canvas = previous.unwrap_or(canvas),
+            _ => (),
+        }
+    }
+    sampler.finish()
+}
+
+/// Picks up to `samples` frames spread evenly over an animation
+pub(crate) struct Sampler {
+    samples: usize,
+    kept: Vec<RgbaImage>,
+    stride: usize,
+    index: usize,
+}
+
+impl Sampler {
+    pub(crate) fn new(samples: usize) -> Sampler {
+        Sampler {
+            samples,
+            kept: Vec::new(),
+            stride: 1,
+            index: 0,
+        }
+    }
+
+    /// Look at the canvas once the next frame is drawn on it.
+    /// The frame count isn't known up front, so every `stride`th frame is
+    /// kept, and they are thinned out whenever there are twice as many as needed.
+    pub(crate) fn offer(&mut self, canvas: &RgbaImage) {
+        if self.index % self.stride == 0 {
+            self.kept.push(canvas.clone());
+            if self.kept.len() >= self.samples * 2 {
+                self.kept = self.kept.drain(..).step_by(2).collect();
+                self.stride *= 2;
+            }
+        }
+        self.index += 1;
+    }
+
+    pub(crate) fn finish(self) -> ImageResult<Vec<DynamicImage>> {
+        let kept = self.kept;
+        if kept.is_empty() {
+            return Err(ImageError::ImageEnd);
+        }
+        let chosen = self.samples.max(1).min(kept.len());
+        Ok((0..chosen)
+            .map(|i| DynamicImage::ImageRgba8(kept[i * kept.len() / chosen].clone()))
+            .collect())
+    }
+}
+
+/// Decode a JPEG at the smallest scale that is still at least a thumbnail
+fn jpeg_thumbnail<R: Read>(data: R) -> ImageResult<Decoded> {
+    let to_image_error = |e: jpeg_decoder::Error| ImageError::FormatError(e.to_string());
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    decoder.read_info().map_err(to_image_error)?;
+    // Scaling changes the size the decoder reports, so take it first
+    let info = decoder.info().ok_or(ImageError::NotEnoughData)?;
+    let (width, height) = decoder
+        .scale(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        .map_err(to_image_error)?;
+    let pixels = decoder.decode().map_err(to_image_error)?;
+
+    let (width, height) = (u32::from(width), u32::from(height));
+    let frame = match info.pixel_format {
+        PixelFormat::L8 => GrayImage::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8),
+        PixelFormat::RGB24 => {
+            RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+        }
+        PixelFormat::CMYK32 => {
+            RgbImage::from_raw(width, height, cmyk_to_rgb(&pixels)).map(DynamicImage::ImageRgb8)
+        }
+    }
+    .ok_or(ImageError::DimensionError)?;
+    Ok(Decoded {
+        frames: vec![frame],
+        dimensions: (u32::from(info.width), u32::from(info.height)),
+    })
+}
+
+fn cmyk_to_rgb(pixels: &[u8]) -> Vec<u8> {
+    pixels
+        .chunks(4)
+        .flat_map(|p| {
+            let k = 255 - u16::from(p[3]);
+            let channel = |c: u8| ((255 - u16::from(c)) * k / 255) as u8;
+            vec![channel(p[0]), channel(p[1]), channel(p[2])]
+        })
+        .collect()
+}
+
+/// Paint the opaque pixels of a GIF frame onto the canvas
+fn draw_frame(canvas: &mut RgbaImage, frame: &gif::Frame) {
+    for (i, pixel) in frame.buffer.chunks(4).enumerate() {
+        if pixel[3] == 0 {
+            continue;
+        }
+        let x = u32::from(frame.left) + (i % frame.width as usize) as u32;
+        let y = u32::from(frame.top) + (i / frame.width as usize) as u32;
+        if x < canvas.width() && y < canvas.height() {
+            canvas.put_pixel(
+                x,
+                y,
+                Rgba {
+                    data: [pixel[0], pixel[1], pixel[2], pixel[3]],
+                },
+            );
+        }
+    }
+}
+
+/// Reset the area covered by a GIF frame to transparent
+fn clear_frame(canvas: &mut RgbaImage, frame: &gif::Frame) {
+    let right = (u32::from(frame.left) + u32::from(frame.width)).min(canvas.width());
+    let bottom = (u32::from(frame.top) + u32::from(frame.height)).min(canvas.height());
+    for y in u32::from(frame.top)..bottom {
+        for x in u32::from(frame.left)..right {
+            canvas.put_pixel(x, y, Rgba { data: [0, 0, 0, 0] });
+        }
+    }
+}
+
+/// Decode the largest JPEG preview embedded in a RAW file.
+///
+/// Every RAW format we support stores at least one full JPEG stream
+/// somewhere in the file, so rather than parse each vendor's TIFF layout we
+/// look for start-of-image markers and keep whichever decodes the largest.
+#[cfg(feature = "raw")]
+fn largest_embedded_jpeg(data: &[u8]) -> Option<DynamicImage> {
+    data.windows(3)
+        .enumerate()
+        .filter(|(_, w)| *w == [0xFF, 0xD8, 0xFF])
+        .take(MAX_PREVIEW_CANDIDATES)
+        // The decoder stops at the end-of-image marker, so trailing data is fine
+        .filter_map(|(start, _)| {
+            image::load_from_memory_with_format(&data[start..], ImageFormat::JPEG).ok()
+        })
+        .max_by_key(|i| {
+            let (width, height) = i.dimensions();
+            u64::from(width) * u64::from(height)
+        })
+}
+
+#[cfg(feature = "heif")]
+fn decode_heif(data: &[u8]) -> ImageResult<DynamicImage> {
+    use libheif_rs::{ColorSpace, HeifContext, RgbChroma};
+
+    let to_image_error = |e: libheif_rs::HeifError| ImageError::FormatError(e.to_string());
+    let context = HeifContext::read_from_bytes(data).map_err(to_image_error)?;
+    let handle = context.primary_image_handle().map_err(to_image_error)?;
+    let decoded = handle
+        .decode(ColorSpace::Rgb(RgbChroma::Rgb), false)
+        .map_err(to_image_error)?;
+    let plane = decoded
+        .planes()
+        .interleaved
+        .ok_or_else(|| ImageError::FormatError("HEIF image has no RGB plane".to_string()))?;
+
+    // Rows may be padded, so copy them out one at a time
+    let row_len = plane.width as usize * 3;
+    let mut pixels = Vec::with_capacity(row_len * plane.height as usize);
+    for row in plane.data.chunks(plane.stride).take(plane.height as usize) {
+        pixels.extend_from_slice(&row[..row_len]);
+    }
+    RgbImage::from_raw(plane.width, plane.height, pixels)
+        .map(DynamicImage::ImageRgb8)
+        .ok_or(ImageError::DimensionError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{jpeg::JPEGEncoder, ColorType};
+    use std::fs::File;
+    use std::path::PathBuf;
+
+    fn hash_frames(path: &Path, samples: usize) -> ImageResult<Decoded> {
+        read_encoded(path).unwrap().decode_frames(path, samples)
+    }
+
+    #[test]
+    fn stills_have_one_frame() {
+        let decoded = hash_frames(&PathBuf::from("test/rustBsquish500.jpg"), 8).unwrap();
+        assert_eq!(decoded.frames.len(), 1);
+    }
+
+    #[test]
+    fn frames_to_show_are_full_size() {
+        let path = PathBuf::from("test/rustA500.jpg");
+        let frames = open_frames(&path, 8).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].dimensions(), open(&path).unwrap().dimensions());
+
+        let frames = open_frames(&PathBuf::from("test/animated.webp"), 8).unwrap();
+        assert!(frames.len() > 1);
+    }
+
+    #[test]
+    fn jpegs_are_shrunk_but_keep_dimensions() {
+        let encoded = Encoded::new(Path::new("big.jpg"), jpeg(2048, 1536));
+        let full_size = 2048 * 1536 * 4;
+        assert!(encoded.estimated_memory(8) < full_size);
+
+        let decoded = encoded.decode_frames(Path::new("big.jpg"), 8).unwrap();
+        assert_eq!(decoded.dimensions, (2048, 1536));
+        let (width, height) = decoded.frames[0].dimensions();
+        assert!(width < 2048 && height < 1536);
+        assert!(width.max(height) >= u32::from(THUMBNAIL_SIZE));
+    }
+
+    #[test]
+    fn estimates_memory_from_headers() {
+        let mut png = Vec::new();
+        image::png::PNGEncoder::new(&mut png)
+            .encode(&vec![0; 300 * 200 * 3], 300, 200, ColorType::RGB(8))
+            .unwrap();
+        let encoded = Encoded::new(Path::new("small.png"), png);
+        assert_eq!(encoded.estimated_memory(8), 300 * 200 * 4);
+    }
+
+    #[test]
+    fn samples_animation_frames() {
+        let name = format!("img_dedup_animation_test_{}.gif", std::process::id());
+        let path = std::env::temp_dir().join(name);
+        {
+            let mut encoder = gif::Encoder::new(File::create(&path).unwrap(), 4, 4, &[]).unwrap();
+            for shade in 0..20u8 {
+                let mut pixels = vec![shade * 10, 0, 0, 255].repeat(16);
+                encoder
+                    .write_frame(&gif::Frame::from_rgba(4, 4, &mut pixels))
+                    .unwrap();
+            }
+        }
+        let frames = hash_frames(&path, 8).unwrap().frames;
+        assert_eq!(frames.len(), 8);
+        assert_eq!(frames[0].dimensions(), (4, 4));
+        assert_ne!(frames[0].raw_pixels(), frames[7].raw_pixels());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        let pixels = vec![127; (width * height * 3) as usize];
+        JPEGEncoder::new(&mut out)
+            .encode(&pixels, width, height, ColorType::RGB(8))
+            .unwrap();
+        out
+    }
+
+    #[cfg(feature = "raw")]
+    #[test]
+    fn finds_largest_preview() {
+        let mut raw = b"II*\x00 not really a tiff header".to_vec();
+        raw.extend(jpeg(16, 12));
+        raw.extend(vec![0x42; 64]);
+        raw.extend(jpeg(64, 48));
+        raw.extend(vec![0x13; 64]);
+
+        let preview = largest_embedded_jpeg(&raw).unwrap();
+        assert_eq!(preview.dimensions(), (64, 48));
+    }
+
+    #[cfg(feature = "raw")]
+    #[test]
+    fn no_preview() {
+        assert!(largest_embedded_jpeg(&[0x00, 0xFF, 0xD8, 0x00]).is_none());
+    }
+}
@@ -0,0 +1,108 @@
+//! Images that make the differences between two near duplicates visible
+
+use image::{DynamicImage, FilterType, GenericImage, ImageBuffer, Rgb, RgbImage};
+
+/// Longest side of the aligned images.  Differences are for looking at,
+/// so there is no need to compare huge photos at full size.
+const MAX_SIDE: u32 = 2048;
+
+/// How much small differences are exaggerated in the heatmap
+const HEATMAP_GAIN: u32 = 4;
+
+/// Resize both images to the same dimensions, those of `left` shrunk to
+/// fit `MAX_SIDE`, so that they can be compared pixel by pixel
+pub fn align(left: &DynamicImage, right: &DynamicImage) -> (RgbImage, RgbImage) {
+    let (width, height) = left.dimensions();
+    let scale = (f64::from(MAX_SIDE) / f64::from(width.max(height))).min(1.0);
+    let width = ((f64::from(width) * scale) as u32).max(1);
+    let height = ((f64::from(height) * scale) as u32).max(1);
+    let resize = |image: &DynamicImage| {
+        if image.dimensions() == (width, height) {
+            image.to_rgb()
+        } else {
+            image
+                .resize_exact(width, height, FilterType::Triangle)
+                .to_rgb()
+        }
+    };
+    (resize(left), resize(right))
+}
+
+/// Absolute difference of two aligned images.  Identical pixels are black,
+/// and differences glow red, then yellow, then white as they grow.
+pub fn heatmap(left: &RgbImage, right: &RgbImage) -> RgbImage {
+    ImageBuffer::from_fn(left.width(), left.height(), |x, y| {
+        let (a, b) = (left.get_pixel(x, y), right.get_pixel(x, y));
+        let difference: u32 = a
+            .data
+            .iter()
+            .zip(b.data.iter())
+            .map(|(&a, &b)| u32::from(if a > b { a - b } else { b - a }))
+            .sum();
+        let heat = (difference * HEATMAP_GAIN).min(765);
+        let channel = |offset: u32| heat.saturating_sub(offset).min(255) as u8;
+        Rgb {
+            data: [channel(0), channel(255), channel(510)],
+        }
+    })
+}
+
+/// The left image up to `position` (from 0 to 1) of the way across, and the
+/// right image after it, split by a thin white line
+pub fn wipe(left: &RgbImage, right: &RgbImage, position: f64) -> RgbImage {
+    let split = (f64::from(left.width()) * position.max(0.0).min(1.0)) as u32;
+    ImageBuffer::from_fn(left.width(), left.height(), |x, y| {
+        if x == split {
+            Rgb {
+                data: [255, 255, 255],
+            }
+        } else if x < split {
+            *left.get_pixel(x, y)
+        } else {
+            *right.get_pixel(x, y)
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solid(width: u32, height: u32, value: u8) -> DynamicImage {
+        DynamicImage::ImageRgb8(ImageBuffer::from_pixel(
+            width,
+            height,
+            Rgb {
+                data: [value, value, value],
+            },
+        ))
+    }
+
+    #[test]
+    fn aligns_to_the_left_image() {
+        let (left, right) = align(&solid(40, 20, 0), &solid(10, 10, 0));
+        assert_eq!(left.dimensions(), (40, 20));
+        assert_eq!(right.dimensions(), (40, 20));
+        let (left, _) = align(&solid(4096, 1024, 0), &solid(10, 10, 0));
+        assert_eq!(left.dimensions(), (MAX_SIDE, MAX_SIDE / 4));
+    }
+
+    #[test]
+    fn identical_images_have_a_black_heatmap() {
+        let (left, right) = align(&solid(8, 8, 100), &solid(8, 8, 100));
+        assert!(heatmap(&left, &right).pixels().all(|p| p.data == [0, 0, 0]));
+        let (left, right) = align(&solid(8, 8, 0), &solid(8, 8, 255));
+        assert!(heatmap(&left, &right)
+            .pixels()
+            .all(|p| p.data == [255, 255, 255]));
+    }
+
+    #[test]
+    fn wipes_from_left_to_right() {
+        let (left, right) = align(&solid(10, 1, 0), &solid(10, 1, 200));
+        let wiped = wipe(&left, &right, 0.5);
+        assert_eq!(wiped.get_pixel(0, 0).data, [0, 0, 0]);
+        assert_eq!(wiped.get_pixel(5, 0).data, [255, 255, 255]);
+        assert_eq!(wiped.get_pixel(9, 0).data, [200, 200, 200]);
+    }
+}
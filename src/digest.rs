@@ -0,0 +1,114 @@
+use crate::ScanError;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest as _, Sha256};
+use std::fmt;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::str::FromStr;
+
+/// How much of each end of a file `Digest::sample` reads
+const SAMPLE_LEN: u64 = 1024 * 1024;
+
+/// SHA-256 of the contents of a file, which identifies it even after it
+/// has been renamed or moved.  Written as hex in saved files.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct Digest([u8; 32]);
+
+impl Digest {
+    /// The digest of some bytes
+    pub fn of(data: &[u8]) -> Digest {
+        Digest::from_hash(&Sha256::digest(data))
+    }
+
+    /// The digest of everything left in `reader`, without holding it all in memory
+    pub fn read(reader: &mut impl Read) -> io::Result<Digest> {
+        let mut hasher = Sha256::new();
+        io::copy(reader, &mut hasher)?;
+        Ok(Digest::from_hash(&hasher.result()))
+    }
+
+    /// A digest of the length of `file` and its first and last MiB, for
+    /// files such as videos that are too big to read in full on every
+    /// scan.  Files that differ only in between get the same digest.
+    pub fn sample<F: Read + Seek>(file: &mut F) -> io::Result<Digest> {
+        let len = file.seek(SeekFrom::End(0))?;
+        let mut hasher = Sha256::new();
+        hasher.input(&len.to_le_bytes());
+        file.seek(SeekFrom::Start(0))?;
+        io::copy(&mut file.by_ref().take(SAMPLE_LEN), &mut hasher)?;
+        if len > SAMPLE_LEN * 2 {
+            file.seek(SeekFrom::End(-(SAMPLE_LEN as i64)))?;
+        }
+        io::copy(&mut file.by_ref().take(SAMPLE_LEN), &mut hasher)?;
+        Ok(Digest::from_hash(&hasher.result()))
+    }
+
+    fn from_hash(hash: &[u8]) -> Digest {
+        let mut bytes = [0; 32];
+        bytes.copy_from_slice(hash);
+        Digest(bytes)
+    }
+}
+
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&hex::encode(self.0))
+    }
+}
+
+impl FromStr for Digest {
+    type Err = ScanError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match hex::decode(s) {
+            Ok(ref bytes) if bytes.len() == 32 => Ok(Digest::from_hash(bytes)),
+            _ => Err(ScanError::InvalidConfig {
+                reason: format!("{:?} is not a SHA-256 digest", s),
+            }),
+        }
+    }
+}
+
+impl Serialize for Digest {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Digest {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn digests_round_trip_as_hex() {
+        let digest = Digest::of(b"abc");
+        assert_eq!(
+            digest.to_string(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(digest.to_string().parse::<Digest>().unwrap(), digest);
+        assert_eq!(Digest::read(&mut &b"abc"[..]).unwrap(), digest);
+        assert!("abc".parse::<Digest>().is_err());
+    }
+
+    #[test]
+    fn samples_depend_on_the_ends_and_length() {
+        let len = (SAMPLE_LEN * 3) as usize;
+        let sample = |data: &[u8]| Digest::sample(&mut io::Cursor::new(data)).unwrap();
+        let data = vec![0; len];
+        let mut middle = data.clone();
+        middle[len / 2] = 1;
+        let mut end = data.clone();
+        end[len - 1] = 1;
+
+        assert_eq!(sample(&data), sample(&middle));
+        assert_ne!(sample(&data), sample(&end));
+        assert_ne!(sample(&data), sample(&data[1..]));
+        assert_ne!(sample(b"abc"), Digest::of(b"abc"));
+    }
+}
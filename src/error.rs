@@ -0,0 +1,99 @@
+use failure::Fail;
+use image::ImageError;
+use std::io;
+use std::path::PathBuf;
+
+/// Why a scan or an image could not be finished
+#[derive(Debug, Fail)]
+pub enum ScanError {
+    /// The directory held nothing we know how to read
+    #[fail(display = "No readable image files found in {:?}", directory)]
+    NoImages {
+        /// The directory that was scanned
+        directory: PathBuf,
+    },
+    /// A file or directory could not be read
+    #[fail(display = "Cannot read {:?}: {}", path, cause)]
+    Io {
+        /// What was being read
+        path: PathBuf,
+        /// The underlying error
+        #[cause]
+        cause: io::Error,
+    },
+    /// A file was read but is not an image we can decode
+    #[fail(display = "Cannot decode {:?}: {}", path, cause)]
+    Decode {
+        /// The file that was decoded
+        path: PathBuf,
+        /// The underlying error
+        #[cause]
+        cause: ImageError,
+    },
+    /// The configuration cannot be used as it is
+    #[fail(display = "Invalid configuration: {}", reason)]
+    InvalidConfig {
+        /// What is wrong with it
+        reason: String,
+    },
+    /// A saved review session could not be read
+    #[fail(display = "Cannot open session {:?}: {}", path, reason)]
+    InvalidSession {
+        /// The session file
+        path: PathBuf,
+        /// What is wrong with it
+        reason: String,
+    },
+    /// A file changed after it was scanned, so what was decided about it
+    /// may no longer hold
+    #[fail(display = "{:?} changed since it was scanned", path)]
+    Changed {
+        /// The file that changed or went missing
+        path: PathBuf,
+    },
+    /// The scan was stopped before it finished
+    #[fail(display = "The scan was cancelled")]
+    Cancelled,
+}
+
+impl ScanError {
+    /// Attach the path to an error from one of the image decoders
+    pub(crate) fn from_image(path: PathBuf, error: ImageError) -> ScanError {
+        match error {
+            ImageError::IoError(cause) => ScanError::Io { path, cause },
+            cause => ScanError::Decode { path, cause },
+        }
+    }
+
+    /// What went wrong, leaving out the path, for showing next to it
+    pub(crate) fn reason(&self) -> String {
+        match self {
+            ScanError::Io { cause, .. } => cause.to_string(),
+            ScanError::Decode { cause, .. } => cause.to_string(),
+            e => e.to_string(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_errors_are_kept_apart() {
+        let path = PathBuf::from("missing.jpg");
+        let missing = io::Error::new(io::ErrorKind::NotFound, "gone");
+        match ScanError::from_image(path.clone(), ImageError::IoError(missing)) {
+            ScanError::Io { path: p, ref cause } => {
+                assert_eq!(p, path);
+                assert_eq!(cause.to_string(), "gone");
+            }
+            e => panic!("Expected an I/O error, got {}", e),
+        }
+        let bad = ImageError::FormatError("garbage".to_string());
+        match ScanError::from_image(path, bad) {
+            ScanError::Decode { .. } => {}
+            e => panic!("Expected a decode error, got {}", e),
+        }
+    }
+}
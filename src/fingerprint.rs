@@ -0,0 +1,89 @@
+use bit_vec::BitVec;
+use image::DynamicImage;
+use img_hash::{HashType as InnerHashType, ImageHash};
+
+/// The perceptual hashes of an image.
+///
+/// Stills have a single hash.  Animations have one for each sampled frame so
+/// that two animations that merely start on the same frame are not considered
+/// duplicates.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Fingerprint {
+    frames: Vec<BitVec>,
+}
+
+impl Fingerprint {
+    /// Hash every frame of an image
+    pub fn new(frames: &[DynamicImage], hash_size: u32, method: InnerHashType) -> Fingerprint {
+        Fingerprint {
+            frames: frames
+                .iter()
+                .map(|frame| ImageHash::hash(frame, hash_size, method).bitv)
+                .collect(),
+        }
+    }
+
+    /// Build a fingerprint from hashes that have already been computed
+    pub fn from_hashes(frames: Vec<BitVec>) -> Fingerprint {
+        Fingerprint { frames }
+    }
+
+    /// Length of the hash of a single frame
+    pub fn len(&self) -> usize {
+        self.frames.first().map_or(0, BitVec::len)
+    }
+
+    /// Whether there is nothing hashed to compare
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Number of matching bits, averaged over the frames.
+    ///
+    /// Sequences of different lengths are stretched to the longer one, so a
+    /// still is compared against every sampled frame of an animation.
+    /// Nothing matches a fingerprint without frames.
+    pub fn similarity(&self, other: &Fingerprint) -> usize {
+        if self.frames.is_empty() || other.frames.is_empty() {
+            return 0;
+        }
+        let samples = self.frames.len().max(other.frames.len());
+        let total: usize = (0..samples)
+            .map(|i| {
+                dist(
+                    &self.frames[i * self.frames.len() / samples],
+                    &other.frames[i * other.frames.len() / samples],
+                )
+            })
+            .sum();
+        total / samples
+    }
+}
+
+// For 100 images, this will be called 5000 times
+// For 1000, this will be called 500,000 times.
+// For 10,000 at 115ns/iter that's only 5 seconds.
+// So we're probably okay, even at 64-byte arrays
+
+/// Return distance between the vectors
+fn dist(a: &BitVec, b: &BitVec) -> usize {
+    a.iter()
+        .zip(b.iter())
+        .filter(|&(left, right)| left == right)
+        .count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nothing_matches_an_empty_fingerprint() {
+        let hashed = Fingerprint::from_hashes(vec![BitVec::from_bytes(&[0b1010_1010])]);
+        let empty = Fingerprint::from_hashes(Vec::new());
+        assert!(empty.is_empty() && !hashed.is_empty());
+        assert_eq!(hashed.similarity(&hashed), 8);
+        assert_eq!(hashed.similarity(&empty), 0);
+        assert_eq!(empty.similarity(&hashed), 0);
+    }
+}
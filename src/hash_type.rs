@@ -75,16 +75,6 @@ impl FromStr for HashType {
     }
 }
 
-impl From<&str> for HashType {
-    fn from(s: &str) -> Self {
-        // TODO: Case Insensitive... maybe UniCase?
-        match HASH_TYPES.get(s) {
-            Some(wrapper) => HashType::new(wrapper.hash_type),
-            None => panic!("Invalid string"),
-        }
-    }
-}
-
 impl Default for HashType {
     fn default() -> HashType {
         HashType::new(DEFAULT_METHOD)
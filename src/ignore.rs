@@ -0,0 +1,128 @@
+use crate::session::replace_file;
+use crate::{Digest, ScanError, SimilarPair};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Pairs of files the user has said are not duplicates, so that they are
+/// left out of later scans.  Files are known by their digest, so renaming
+/// or moving them does not bring the pair back.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct IgnoreList {
+    /// Each pair is kept with the smaller digest first
+    pairs: BTreeSet<(Digest, Digest)>,
+}
+
+/// The ignore list in the user's data directory,
+/// e.g. `~/.local/share/img_dedup/ignored.json`
+fn user_file() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("img_dedup").join("ignored.json"))
+}
+
+impl IgnoreList {
+    /// Read the user's ignore list, which is empty until something is ignored
+    pub fn load() -> Result<IgnoreList, ScanError> {
+        match user_file() {
+            Some(path) => IgnoreList::read(&path),
+            None => Ok(IgnoreList::default()),
+        }
+    }
+
+    /// Save the list as the user's ignore list, returning where it was saved
+    pub fn save(&self) -> Result<PathBuf, ScanError> {
+        let path = user_file().ok_or_else(|| ScanError::InvalidConfig {
+            reason: "No data directory to save ignored pairs in".to_string(),
+        })?;
+        self.write(&path)?;
+        Ok(path)
+    }
+
+    /// Whether the images of `pair` were said not to be duplicates
+    pub fn is_ignored(&self, pair: &SimilarPair) -> bool {
+        self.pairs.contains(&key(pair))
+    }
+
+    /// Leave `pair` out of later scans
+    pub fn ignore(&mut self, pair: &SimilarPair) {
+        self.pairs.insert(key(pair));
+    }
+
+    /// Stop leaving `pair` out of scans
+    pub fn unignore(&mut self, pair: &SimilarPair) {
+        self.pairs.remove(&key(pair));
+    }
+
+    /// How many pairs are ignored
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Whether no pairs are ignored
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    fn read(path: &Path) -> Result<IgnoreList, ScanError> {
+        if !path.is_file() {
+            return Ok(IgnoreList::default());
+        }
+        let text = fs::read_to_string(path).map_err(|cause| ScanError::Io {
+            path: path.to_path_buf(),
+            cause,
+        })?;
+        serde_json::from_str(&text).map_err(|e| ScanError::InvalidConfig {
+            reason: format!("{:?}: {}", path, e),
+        })
+    }
+
+    fn write(&self, path: &Path) -> Result<(), ScanError> {
+        let io_error = |cause| ScanError::Io {
+            path: path.to_path_buf(),
+            cause,
+        };
+        let text = serde_json::to_string_pretty(self).map_err(|e| io_error(e.into()))?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(io_error)?;
+        }
+        replace_file(path, text.as_bytes()).map_err(io_error)
+    }
+}
+
+/// A pair's digests in order, so it is found whichever side each image is on
+fn key(pair: &SimilarPair) -> (Digest, Digest) {
+    let (left, right) = (pair.left.digest(), pair.right.digest());
+    (left.min(right), left.max(right))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Classification, SimilarImage};
+    use std::sync::Arc;
+
+    #[test]
+    fn ignores_pairs_either_way_round() {
+        let image = |name: &str| Arc::new(SimilarImage::test_image(PathBuf::from(name)));
+        let (a, b, c) = (image("a.jpg"), image("b.jpg"), image("c.jpg"));
+        let pair = |x: &Arc<SimilarImage>, y: &Arc<SimilarImage>| {
+            SimilarPair::new(10, x.clone(), y.clone(), Classification::Edited)
+        };
+
+        let mut list = IgnoreList::default();
+        list.ignore(&pair(&a, &b));
+        assert!(list.is_ignored(&pair(&b, &a)));
+        assert!(!list.is_ignored(&pair(&a, &c)));
+
+        let path =
+            std::env::temp_dir().join(format!("img_dedup_ignored_{}.json", std::process::id()));
+        list.write(&path).unwrap();
+        let read = IgnoreList::read(&path);
+        fs::remove_file(&path).unwrap();
+        let mut read = read.unwrap();
+        assert_eq!(read, list);
+
+        read.unignore(&pair(&b, &a));
+        assert!(read.is_empty());
+    }
+}
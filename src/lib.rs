@@ -13,28 +13,84 @@
     unused_qualifications
 )]
 
+mod archive;
+#[cfg(feature = "avif")]
+mod avif;
+mod color;
 mod config;
+mod decode;
+mod diff;
+mod digest;
+mod error;
+mod fingerprint;
 mod hash_type;
+mod ignore;
+mod metadata;
+mod preset;
+mod profile;
+mod progress;
+mod review;
+mod session;
 mod similar_image;
+mod stream;
+mod summary;
+mod throttle;
+mod thumbnail;
+mod video;
+mod webp;
 
-use bit_vec::BitVec;
-use failure::{format_err, Error};
-use img_hash::ImageHash;
-use itertools::Itertools;
 use log::{debug, info, warn};
 use rayon::prelude::*;
-use std::{cell::RefCell, collections::BinaryHeap, path::PathBuf, rc::Rc, sync::mpsc::Sender};
-use walkdir::WalkDir;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, sync_channel, Receiver, Sender, SyncSender};
+use std::sync::{Arc, Mutex};
+use std::{collections::BinaryHeap, thread};
+use walkdir::{DirEntry, WalkDir};
 
+pub use self::color::ColorSignature;
 pub use self::config::Config;
+pub use self::diff::{align, heatmap, wipe};
+pub use self::digest::Digest;
+pub use self::error::ScanError;
+pub use self::fingerprint::Fingerprint;
 pub use self::hash_type::{HashType, InnerHashType};
-pub use self::similar_image::{SimilarImage, SimilarPair};
+pub use self::ignore::IgnoreList;
+pub use self::metadata::Metadata;
+pub use self::preset::Preset;
+pub use self::progress::{Phase, Progress, StatusMsg};
+pub use self::review::{Decision, Filter, Review, SortOrder};
+pub use self::similar_image::{Classification, SimilarImage, SimilarPair};
+pub use self::stream::{stream_pairs, PairStream};
+pub use self::summary::Summary;
+use self::throttle::{Permit, Throttle};
+pub use self::thumbnail::find_thumbnail;
 
-/// Taken from the image crate's list of valid images
-const VALID_IMAGES: [&str; 15] = [
-    "jpg", "jpeg", "png", "gif", "webp", "tif", "tiff", "tga", "bmp", "ico", "hdr", "pbm", "pam",
-    "pgm", "ppm",
-];
+/// Open a single image with any of the supported decoders.
+/// The path may point inside an archive, and videos are represented by a
+/// keyframe from their middle.
+pub fn open_image(path: &Path) -> Result<image::DynamicImage, ScanError> {
+    decode::open(path).map_err(|e| ScanError::from_image(path.to_path_buf(), e))
+}
+
+/// Open an image to show it, with up to `samples` frames spread over an
+/// animated GIF or WebP.  Anything else has the single frame `open_image` gives.
+pub fn open_frames(path: &Path, samples: usize) -> Result<Vec<image::DynamicImage>, ScanError> {
+    decode::open_frames(path, samples).map_err(|e| ScanError::from_image(path.to_path_buf(), e))
+}
+
+/// How many frames of an animation or video are hashed
+const ANIMATION_SAMPLES: usize = 8;
+
+/// How many files may wait in memory between being read and being hashed
+const READ_AHEAD: usize = 16;
+
+/// The memory budget is split so that files read ahead get this fraction,
+/// and decoding the rest.  Neither can then wait on the other for memory.
+const READ_AHEAD_SHARE: u64 = 4;
+
+/// How many files are found between updates of the total
+const DISCOVERY_BATCH: usize = 100;
 
 /// Scan image files in a directory
 /// Outputs an priority queue of close matches
@@ -44,34 +100,159 @@ pub fn scan_files(
     method: HashType,
     hash_size: u32,
     sender: Sender<StatusMsg>,
-) -> Result<BinaryHeap<SimilarPair>, Error> {
-    let files_to_process = discover_files(dir);
-    if files_to_process.is_empty() {
-        return Err(format_err!("No readable image files found in {:#?}", dir));
+) -> Result<BinaryHeap<SimilarPair>, ScanError> {
+    let config = Config {
+        directory: dir.clone(),
+        method,
+        hash_size,
+        ..Config::default()
+    };
+    scan(&config, &IgnoreList::default(), sender)
+}
+
+/// Scan image files with every setting in `config`, leaving out the pairs
+/// in `ignored`.  Otherwise the same as `scan_files`.
+pub fn scan(
+    config: &Config,
+    ignored: &IgnoreList,
+    sender: Sender<StatusMsg>,
+) -> Result<BinaryHeap<SimilarPair>, ScanError> {
+    let mut pairs = Vec::new();
+    scan_pairs(config, ignored, sender.clone(), |pair| {
+        pairs.push(pair);
+        true
+    })?;
+
+    send_status(&sender, StatusMsg::Phase(Phase::Grouping));
+    Ok(pairs.into_iter().collect())
+}
+
+/// Scan image files in the configured directory, handing every pair to
+/// `on_pair` as soon as both of its images are hashed.
+/// Pairs come in no particular order, while the scan is still running.
+/// Returning `false` from `on_pair` cancels the scan.
+/// Pairs in `ignored` are left out.
+pub fn scan_pairs<F>(
+    config: &Config,
+    ignored: &IgnoreList,
+    sender: Sender<StatusMsg>,
+    mut on_pair: F,
+) -> Result<(), ScanError>
+where
+    F: FnMut(SimilarPair) -> bool,
+{
+    config.validate()?;
+    let dir = &config.directory;
+    if let Err(cause) = dir.read_dir() {
+        return Err(ScanError::Io {
+            path: dir.clone(),
+            cause,
+        });
+    }
+    let include_video = config.video && video::ffmpeg_available();
+    if config.video && !include_video {
+        warn!("ffmpeg and ffprobe were not found, skipping videos");
     }
-    debug!("List of files found: {:#?}", files_to_process);
-
-    // Alert the GUI how many need to be processed.
-    // I originally performed this type of communication of this via two AtomicU32's.
-    // My concern with messages was that this might scan too quickly for the GUI to keep up and it
-    // would create a backlog of messages.  I've decided to go with messages for these reasons:
-    // 1. "Do not communicate by sharing memory; instead, share memory by communicating." -- Go people
-    // 2. My tests show that updating the GUI takes barely any time at all.
-    //    Meanwhile, hashing tiny images takes about 50ms, and even medium images take a few hundred.
-    // 3. Relm (my GUI library) is event-based.  I would end up having to poll the shared values.
-    sender
-        .send(StatusMsg::Total(files_to_process.len()))
-        .unwrap();
 
-    let hashes = hash_files(files_to_process, hash_size, method, sender);
+    // 0 threads lets rayon pick one per CPU
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(config.threads)
+        .build()
+        .map_err(|e| ScanError::InvalidConfig {
+            reason: format!("Cannot start {} threads: {}", config.threads, e),
+        })?;
 
-    Ok(sort_ham(hashes))
+    // Walk the directory on its own thread, so hashing can start with the first file found
+    send_status(&sender, StatusMsg::Phase(Phase::Discovering));
+    let (found_sender, found_receiver) = channel();
+    let discovery = {
+        let (dir, sender) = (dir.clone(), sender.clone());
+        let (archives, ordered_reads) = (config.archives, config.ordered_reads);
+        thread::spawn(move || {
+            let found = discover_files(
+                &dir,
+                include_video,
+                archives,
+                ordered_reads,
+                &found_sender,
+                &sender,
+            );
+            send_status(&sender, StatusMsg::Total(found));
+            send_status(&sender, StatusMsg::Phase(Phase::Hashing));
+            found
+        })
+    };
+
+    // Hash on the pool while this thread compares each image as it comes in
+    let (hashed_sender, hashed_receiver) = channel();
+    let hashing = {
+        let (config, sender) = (config.clone(), sender.clone());
+        thread::spawn(move || {
+            pool.install(|| hash_files(found_receiver, &config, sender.clone(), hashed_sender));
+            // Whatever is left in the queue still has to be compared
+            send_status(&sender, StatusMsg::Phase(Phase::Comparing));
+        })
+    };
+    // Pairs the user said are not duplicates are dropped as they are found
+    let compared = compare_hashes(hashed_receiver, &sender, |pair| {
+        ignored.is_ignored(&pair) || on_pair(pair)
+    });
+
+    // Dropping the receiving ends above lets the other threads wind down early
+    hashing.join().unwrap();
+    let found = discovery.join().unwrap();
+    compared?;
+    if found == 0 {
+        return Err(ScanError::NoImages {
+            directory: dir.clone(),
+        });
+    }
+    Ok(())
+}
+
+/// Something found by the directory walk for the readers to read
+enum Found {
+    /// An image file on disk
+    File(PathBuf),
+    /// An archive and the names of the images inside it, which are all
+    /// read in one pass
+    Archive(PathBuf, Vec<String>),
+}
+
+impl Found {
+    /// How many images there are to hash
+    fn len(&self) -> usize {
+        match self {
+            Found::File(_) => 1,
+            Found::Archive(_, names) => names.len(),
+        }
+    }
 }
 
-fn discover_files(dir: &PathBuf) -> Vec<PathBuf> {
+/// Status messages are non-essential, so a closed channel is only logged
+fn send_status(sender: &Sender<StatusMsg>, msg: StatusMsg) {
+    sender
+        .send(msg)
+        .unwrap_or_else(|_| warn!("Could not send status message to other side"));
+}
+
+/// Send every image file under `dir` to `found` as soon as it is seen,
+/// and return how many there were
+fn discover_files(
+    dir: &PathBuf,
+    include_video: bool,
+    include_archives: bool,
+    ordered_reads: bool,
+    found: &Sender<Found>,
+    sender: &Sender<StatusMsg>,
+) -> usize {
     info!("Scanning {:?}", dir);
-    WalkDir::new(dir)
-        .follow_links(false) // no symlinks (TODO: Allow via config?)
+    let mut walker = WalkDir::new(dir).follow_links(false); // no symlinks (TODO: Allow via config?)
+    if ordered_reads {
+        // Inode order is a good guess at on-disk order, which saves seeking on spinning disks
+        walker = walker.sort_by(|a, b| disk_order(a).cmp(&disk_order(b)));
+    }
+    let files = walker
         .into_iter()
         .filter_map(|f| match f {
             Ok(f) => Some(f),
@@ -81,84 +262,228 @@ fn discover_files(dir: &PathBuf) -> Vec<PathBuf> {
             }
         }) // only files that can be accessed
         .filter(|f| !f.file_type().is_dir()) // no directories, only images
-        .filter(|f| {
-            VALID_IMAGES.contains(
-                &f.path()
-                    .extension()
-                    .and_then(|s| s.to_str())
-                    .map_or("".to_string(), |s| s.to_ascii_lowercase())
-                    .as_str(),
-            )
-        })
-        .map(|f| f.path().to_path_buf()) // convert to PathBufs
-        .collect()
+        .flat_map(|f| {
+            let path = f.path();
+            if include_archives && archive::is_archive(path) {
+                // Images inside get virtual paths like backup.zip!/DCIM/IMG_1.jpg
+                match archive::list(path) {
+                    Ok(names) => Some(Found::Archive(path.to_path_buf(), names)),
+                    Err(e) => {
+                        warn!("Cannot read archive {:?}: {}", path, e);
+                        None
+                    }
+                }
+            } else if decode::is_supported(path, include_video) {
+                Some(Found::File(path.to_path_buf())) // convert to PathBufs
+            } else {
+                None
+            }
+        });
+
+    let mut count = 0;
+    for file in files {
+        let batch = count / DISCOVERY_BATCH;
+        count += file.len();
+        match &file {
+            Found::File(path) => debug!("Found {:?}", path),
+            Found::Archive(path, names) => debug!("Found {} in {:?}", names.len(), path),
+        }
+        if found.send(file).is_err() {
+            break; // hashing has given up
+        }
+        // Alert the GUI how many need to be processed.
+        // I originally performed this type of communication of this via two AtomicU32's.
+        // My concern with messages was that this might scan too quickly for the GUI to keep up and it
+        // would create a backlog of messages.  I've decided to go with messages for these reasons:
+        // 1. "Do not communicate by sharing memory; instead, share memory by communicating." -- Go people
+        // 2. My tests show that updating the GUI takes barely any time at all.
+        //    Meanwhile, hashing tiny images takes about 50ms, and even medium images take a few hundred.
+        // 3. Relm (my GUI library) is event-based.  I would end up having to poll the shared values.
+        if count / DISCOVERY_BATCH > batch {
+            send_status(sender, StatusMsg::Total(count));
+        }
+    }
+    count
 }
 
-fn hash_files(
-    files_to_process: Vec<PathBuf>,
-    hash_size: u32,
-    method: HashType,
-    sender: Sender<StatusMsg>,
-) -> Vec<(ImageHash, SimilarImage)> {
-    let inner_method = method.into();
-    files_to_process
-        .into_par_iter()
-        .filter_map(|f| match image::open(&f) {
-            Ok(i) => Some((SimilarImage::new(f, &i), i)),
-            _ => None,
-        })
-        .map_with(sender, |s, (f, i)| {
-            let i = ImageHash::hash(&i, hash_size, inner_method);
-            // TODO: An error here probably shouldn't crash the program
-            // it's a non-essential status pipe
-            s.send(StatusMsg::ImageProcessed)
-                .unwrap_or_else(|_| warn!("Could not send status message to other side"));
-            (i, f)
-        })
-        .collect()
+#[cfg(unix)]
+fn disk_order(entry: &DirEntry) -> u64 {
+    use walkdir::DirEntryExt;
+    entry.ino()
 }
 
-fn sort_ham(hashes: Vec<(ImageHash, SimilarImage)>) -> BinaryHeap<SimilarPair> {
-    hashes
-        .into_iter()
-        .map(|(hash, image)| (hash.bitv, Rc::new(RefCell::new(image))))
-        .tuple_combinations() // yikes C(n, 2)
-        // I do not think it is worth the allocation to gain parallelism
-        // .collect::<Vec<_>>()
-        // .into_par_iter()
-        .map(|((hash_a, image_a), (hash_b, image_b))| {
-            SimilarPair::new(dist(&hash_a, &hash_b), image_a, image_b)
+#[cfg(not(unix))]
+fn disk_order(entry: &DirEntry) -> std::ffi::OsString {
+    entry.file_name().to_os_string()
+}
+
+/// Read files on their own threads, so that disk access can be limited
+/// separately, and hash them on the current rayon pool.
+/// Files may still be arriving while the first ones are hashed,
+/// and each is sent to `hashed` as soon as it is done.
+/// Once nobody is listening on `hashed`, the remaining files are skipped.
+fn hash_files<I>(
+    files_to_process: I,
+    config: &Config,
+    sender: Sender<StatusMsg>,
+    hashed: Sender<(Fingerprint, SimilarImage)>,
+) where
+    I: IntoIterator<Item = Found>,
+    I::IntoIter: Send + 'static,
+{
+    let inner_method = config.method.clone().into();
+    let hash_size = config.hash_size;
+    let thumbnails = config.thumbnails;
+    let budget = config.memory_budget.saturating_mul(1024 * 1024);
+    let reading = Throttle::new(budget / READ_AHEAD_SHARE);
+    let memory = Throttle::new(budget - budget / READ_AHEAD_SHARE);
+    let io_threads = match config.io_threads {
+        0 => rayon::current_num_threads(),
+        n => n,
+    };
+
+    let cancelled = Arc::new(AtomicBool::new(false));
+
+    // Readers take files in order, so with a single reader they are read sequentially
+    let queue = Arc::new(Mutex::new(files_to_process.into_iter()));
+    let (read_sender, read_receiver) = sync_channel(READ_AHEAD);
+    let readers: Vec<_> = (0..io_threads)
+        .map(|_| {
+            let (queue, cancelled) = (queue.clone(), cancelled.clone());
+            let (read_sender, reading) = (read_sender.clone(), reading.clone());
+            thread::spawn(move || loop {
+                if cancelled.load(Ordering::Relaxed) {
+                    break;
+                }
+                let next = queue.lock().unwrap().next();
+                let listening = match next {
+                    Some(Found::File(path)) => {
+                        // Held until the file is decoded
+                        let permit = reading.acquire(decode::encoded_size(&path));
+                        let encoded = decode::read_encoded(&path).map_err(|cause| ScanError::Io {
+                            path: path.clone(),
+                            cause,
+                        });
+                        read_sender.send((path, encoded, permit)).is_ok()
+                    }
+                    Some(Found::Archive(path, names)) => {
+                        read_archive(&path, &names, &reading, &read_sender)
+                    }
+                    None => break,
+                };
+                if !listening {
+                    break;
+                }
+            })
         })
-        .collect()
+        .collect();
+    drop(read_sender);
+
+    read_receiver.into_iter().par_bridge().for_each_with(
+        (sender, hashed),
+        |(s, h), (path, encoded, _read)| {
+            if cancelled.load(Ordering::Relaxed) {
+                return;
+            }
+            let bytes = encoded.as_ref().map_or(0, decode::Encoded::size);
+            let digest = encoded.as_ref().ok().map(decode::Encoded::digest);
+            let decoded = encoded.and_then(|e| {
+                // Keep our share of the memory budget until the frames are dropped
+                let _permit = memory.acquire(e.estimated_memory(ANIMATION_SAMPLES));
+                e.decode_frames(&path, ANIMATION_SAMPLES)
+                    .map(|d| (Fingerprint::new(&d.frames, hash_size, inner_method), d))
+                    .map_err(|e| ScanError::from_image(path.clone(), e))
+            });
+            let processed = StatusMsg::ImageProcessed {
+                path: path.clone(),
+                bytes,
+            };
+            send_status(s, processed);
+            match decoded {
+                Ok((hash, d)) => {
+                    if let (true, Some(frame)) = (thumbnails, d.frames.first()) {
+                        if let Err(e) = thumbnail::save(&path, frame) {
+                            debug!("Cannot save a thumbnail of {:?}: {}", path, e);
+                        }
+                    }
+                    let digest = digest.unwrap_or_default();
+                    let image = SimilarImage::new(path, bytes, d.dimensions, &d.frames, digest);
+                    if h.send((hash, image)).is_err() {
+                        cancelled.store(true, Ordering::Relaxed);
+                    }
+                }
+                Err(e) => {
+                    let reason = e.reason();
+                    send_status(s, StatusMsg::Failed { path, reason });
+                }
+            }
+        },
+    );
+    readers.into_iter().for_each(|r| r.join().unwrap());
 }
 
-// For 100 images, this will be called 5000 times
-// For 1000, this will be called 500,000 times.
-// For 10,000 at 115ns/iter that's only 5 seconds.
-// So we're probably okay, even at 64-byte arrays
-
-/// Return distance between the vectors
-fn dist(a: &BitVec, b: &BitVec) -> usize {
-    a.iter()
-        .zip(b.iter())
-        .filter(|&(left, right)| left == right)
-        .count()
+/// A file read by a reader thread, with the permit for the memory it takes
+/// up until it is decoded
+type ReadFile = (PathBuf, Result<decode::Encoded, ScanError>, Permit);
+
+/// Read the images inside an archive in one pass and send them to `read`.
+/// Gives `false` once nobody is listening any more.
+fn read_archive(
+    path: &Path,
+    names: &[String],
+    reading: &Throttle,
+    read: &SyncSender<ReadFile>,
+) -> bool {
+    let mut listening = true;
+    let result = archive::read_each(path, names, |entry, reader, size| {
+        let permit = reading.acquire(size);
+        let encoded = archive::read_entry(&entry, reader, size)
+            .map(|data| decode::Encoded::new(&entry, data));
+        listening = read.send((entry, encoded, permit)).is_ok();
+        listening
+    });
+    if let Err(e) = result {
+        warn!("Cannot read archive {:?}: {}", path, e);
+    }
+    listening
 }
 
-#[derive(Copy, Clone, Debug)]
-/// Status message from scanner
-pub enum StatusMsg {
-    /// All image files have been discovered
-    Total(usize),
-    /// An image has been processed
-    ImageProcessed,
+/// Compare each image against every one hashed before it, as they arrive.
+/// Altogether that is still C(n, 2) comparisons.
+/// Stops early if `on_pair` returns `false`.
+fn compare_hashes<F>(
+    hashed: Receiver<(Fingerprint, SimilarImage)>,
+    sender: &Sender<StatusMsg>,
+    mut on_pair: F,
+) -> Result<(), ScanError>
+where
+    F: FnMut(SimilarPair) -> bool,
+{
+    let mut seen: Vec<(Fingerprint, Arc<SimilarImage>)> = Vec::new();
+    for (hash_b, image_b) in hashed {
+        let image_b = Arc::new(image_b);
+        let count = seen.len() as u64;
+        send_status(sender, StatusMsg::ComparisonTotal(count * (count + 1) / 2));
+        for (hash_a, image_a) in &seen {
+            let similarity = hash_a.similarity(&hash_b);
+            let classification =
+                Classification::classify(similarity, hash_a.len(), image_a, &image_b);
+            let pair =
+                SimilarPair::new(similarity, image_a.clone(), image_b.clone(), classification);
+            if !on_pair(pair) {
+                return Err(ScanError::Cancelled);
+            }
+        }
+        send_status(sender, StatusMsg::PairsCompared(count));
+        seen.push((hash_b, image_b));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use bit_vec::BitVec;
-    use hash_type::InnerHashType;
     use lazy_static::lazy_static;
     use std::path::PathBuf;
     use std::sync::mpsc::channel;
@@ -170,34 +495,29 @@ mod tests {
             PathBuf::from("test/rustA500_copy.jpg"),
             PathBuf::from("test/rustB250.jpg")
         ];
-        static ref TEST_DATA: Vec<(ImageHash, SimilarImage)> = vec![
+        static ref TEST_DATA: Vec<(Fingerprint, SimilarImage)> = vec![
             (
-                ImageHash {
-                    hash_type: InnerHashType::Mean,
-                    bitv: BitVec::from_bytes(&[73, 96, 39, 31, 219, 255, 177, 191]),
-                },
+                Fingerprint::from_hashes(vec![BitVec::from_bytes(&[
+                    73, 96, 39, 31, 219, 255, 177, 191
+                ])]),
                 SimilarImage::test_image(PathBuf::from("test/rustBsquish500.jpg")),
             ),
             (
-                ImageHash {
-                    hash_type: InnerHashType::Mean,
-                    bitv: BitVec::from_bytes(&[227, 235, 255, 249, 243, 120, 62, 60]),
-                },
+                Fingerprint::from_hashes(vec![BitVec::from_bytes(&[
+                    227, 235, 255, 249, 243, 120, 62, 60
+                ])]),
                 SimilarImage::test_image(PathBuf::from("test/rustA500.jpg")),
             ),
             (
-                ImageHash {
-                    hash_type: InnerHashType::Mean,
-
-                    bitv: BitVec::from_bytes(&[227, 235, 255, 249, 243, 120, 62, 60]),
-                },
+                Fingerprint::from_hashes(vec![BitVec::from_bytes(&[
+                    227, 235, 255, 249, 243, 120, 62, 60
+                ])]),
                 SimilarImage::test_image(PathBuf::from("test/rustA500_copy.jpg")),
             ),
             (
-                ImageHash {
-                    hash_type: InnerHashType::Mean,
-                    bitv: BitVec::from_bytes(&[137, 107, 126, 63, 190, 185, 243, 187]),
-                },
+                Fingerprint::from_hashes(vec![BitVec::from_bytes(&[
+                    137, 107, 126, 63, 190, 185, 243, 187
+                ])]),
                 SimilarImage::test_image(PathBuf::from("test/rustB250.jpg")),
             ),
         ];
@@ -206,7 +526,19 @@ mod tests {
     #[test]
     fn test_hash_files() {
         let (sender, _) = channel();
-        let hashed_files = hash_files(TEST_PATHS.to_vec(), 8, "Mean".parse().unwrap(), sender);
+        let mut config = Config::default();
+        config.hash_size = 8;
+        config.method = "Mean".parse().unwrap();
+        let (hashed_sender, hashed) = channel();
+        let files = TEST_PATHS
+            .iter()
+            .cloned()
+            .map(Found::File)
+            .collect::<Vec<_>>();
+        hash_files(files, &config, sender, hashed_sender);
+        // Hashing finishes out of order, so put results back in the order given
+        let mut hashed_files: Vec<_> = hashed.iter().collect();
+        hashed_files.sort_by_key(|(_, img)| TEST_PATHS.iter().position(|p| *p == img.path));
 
         for ((hash1, img1), (hash2, img2)) in TEST_DATA.iter().zip(hashed_files.iter()) {
             assert_eq!(img1.path, img2.path);
@@ -215,23 +547,62 @@ mod tests {
     }
 
     #[test]
-    fn test_sort_ham() {
-        let test_images: Vec<_> = TEST_DATA
-            .iter()
-            .map(|d| Rc::new(RefCell::new(d.1.clone())))
-            .collect();
+    fn test_compare_hashes() {
+        let test_images: Vec<_> = TEST_DATA.iter().map(|d| Arc::new(d.1.clone())).collect();
+        let edited = Classification::Edited;
         let expected_result: [SimilarPair; 6] = [
-            SimilarPair::new(33, test_images[0].clone(), test_images[1].clone()),
-            SimilarPair::new(33, test_images[0].clone(), test_images[2].clone()),
-            SimilarPair::new(37, test_images[1].clone(), test_images[3].clone()),
-            SimilarPair::new(37, test_images[2].clone(), test_images[3].clone()),
-            SimilarPair::new(44, test_images[0].clone(), test_images[3].clone()),
-            SimilarPair::new(64, test_images[1].clone(), test_images[2].clone()),
+            SimilarPair::new(33, test_images[0].clone(), test_images[1].clone(), edited),
+            SimilarPair::new(33, test_images[0].clone(), test_images[2].clone(), edited),
+            SimilarPair::new(37, test_images[1].clone(), test_images[3].clone(), edited),
+            SimilarPair::new(37, test_images[2].clone(), test_images[3].clone(), edited),
+            SimilarPair::new(44, test_images[0].clone(), test_images[3].clone(), edited),
+            SimilarPair::new(
+                64,
+                test_images[1].clone(),
+                test_images[2].clone(),
+                Classification::Exact,
+            ),
         ];
-        let actual_results = sort_ham(TEST_DATA.to_vec()).into_sorted_vec();
+        let (sender, _) = channel();
+        let (hashed_sender, hashed) = channel();
+        TEST_DATA
+            .iter()
+            .for_each(|d| hashed_sender.send(d.clone()).unwrap());
+        drop(hashed_sender);
+        let mut actual_results = BinaryHeap::new();
+        compare_hashes(hashed, &sender, |pair| {
+            actual_results.push(pair);
+            true
+        })
+        .unwrap();
+        let actual_results = actual_results.into_sorted_vec();
         assert_eq!(actual_results.len(), expected_result.len());
         for (pair_a, pair_b) in expected_result.iter().zip(actual_results.into_iter()) {
             assert_eq!(*pair_a, pair_b);
         }
     }
+
+    #[test]
+    fn results_are_thread_safe() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<BinaryHeap<SimilarPair>>();
+    }
+
+    #[test]
+    fn reports_why_a_scan_failed() {
+        let (sender, _receiver) = channel();
+        let mut config = Config::default();
+        let dir = PathBuf::from("test/does_not_exist");
+        match scan_files(&dir, HashType::default(), 8, sender.clone()) {
+            Err(ScanError::Io { path, .. }) => assert_eq!(path, dir),
+            other => panic!("Expected an I/O error, got {:?}", other.map(|p| p.len())),
+        }
+
+        config.directory = std::env::temp_dir().join("img_dedup_empty_test");
+        std::fs::create_dir_all(&config.directory).unwrap();
+        match scan(&config, &IgnoreList::default(), sender) {
+            Err(ScanError::NoImages { .. }) => {}
+            other => panic!("Expected no images, got {:?}", other.map(|p| p.len())),
+        }
+    }
 }
@@ -1,3 +1,4 @@
+mod cli;
 mod win;
 
 use failure::Error;
@@ -5,11 +6,10 @@ use img_dedup::Config;
 use log::{debug, info};
 use relm::Widget;
 use simplelog::{LevelFilter, TermLogger};
-use structopt::StructOpt;
 use win::Win;
 
 fn main() -> Result<(), Error> {
-    let config = Config::from_args();
+    let config = Config::from_args_and_files()?;
 
     let level = match config.verbosity {
         0 => LevelFilter::Error,
@@ -23,6 +23,9 @@ fn main() -> Result<(), Error> {
     info!("Starting Image Deduplicator");
     debug!("{:?}", config);
 
+    if config.no_gui {
+        return cli::run(&config);
+    }
     Win::run(config).unwrap();
     Ok(())
 }
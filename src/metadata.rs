@@ -0,0 +1,155 @@
+use crate::{archive, decode, video, SimilarImage};
+use chrono::{DateTime, Local};
+use exif::{Exif, In, Tag, Value};
+use std::fs::{self, File};
+use std::io::{BufReader, Cursor};
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Details about an image that help decide which copy to keep
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Metadata {
+    /// Width and height in pixels
+    pub dimensions: (u32, u32),
+    /// Size of the file in bytes
+    pub size: u64,
+    /// File format, from the extension
+    pub format: String,
+    /// When the file, or the archive holding it, was last modified
+    pub modified: Option<SystemTime>,
+    /// Camera make and model from EXIF
+    pub camera: Option<String>,
+    /// When the photo was taken, from EXIF
+    pub taken: Option<String>,
+}
+
+impl Metadata {
+    /// Gather the details of an image.  Anything that cannot be read is left out.
+    pub fn read(image: &SimilarImage) -> Metadata {
+        let path = &image.path;
+        let extension = decode::extension(path);
+        let exif = if video::is_video(&extension) {
+            None
+        } else {
+            read_exif(path)
+        };
+        let camera = exif.as_ref().and_then(|exif| {
+            let make = exif_text(exif, Tag::Make);
+            let model = exif_text(exif, Tag::Model);
+            match (make, model) {
+                // Models often repeat the make, e.g. "Canon" and "Canon EOS 80D"
+                (Some(make), Some(model)) if !model.starts_with(&make) => {
+                    Some(format!("{} {}", make, model))
+                }
+                (make, model) => model.or(make),
+            }
+        });
+        Metadata {
+            dimensions: image.dimensions(),
+            size: image.size(),
+            format: extension.to_uppercase(),
+            modified: modified(path),
+            camera,
+            taken: exif.and_then(|exif| exif_text(&exif, Tag::DateTimeOriginal)),
+        }
+    }
+
+    /// Labelled values for display, always in the same order
+    /// so that two images can be compared line by line
+    pub fn describe(&self) -> Vec<(&'static str, String)> {
+        let unknown = || "unknown".to_string();
+        vec![
+            (
+                "Dimensions",
+                format!("{} x {}", self.dimensions.0, self.dimensions.1),
+            ),
+            ("Size", format_size(self.size)),
+            ("Format", self.format.clone()),
+            (
+                "Modified",
+                self.modified.map_or_else(unknown, |time| {
+                    DateTime::<Local>::from(time)
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string()
+                }),
+            ),
+            ("Camera", self.camera.clone().unwrap_or_else(unknown)),
+            ("Taken", self.taken.clone().unwrap_or_else(unknown)),
+        ]
+    }
+}
+
+/// Modification time of a file, or of the archive it is in
+fn modified(path: &Path) -> Option<SystemTime> {
+    let on_disk = archive::split(path).map_or(path.to_path_buf(), |(archive, _)| archive);
+    fs::metadata(on_disk).and_then(|m| m.modified()).ok()
+}
+
+fn read_exif(path: &Path) -> Option<Exif> {
+    let reader = exif::Reader::new();
+    if archive::is_virtual(path) {
+        let data = decode::read(path).ok()?;
+        reader.read_from_container(&mut Cursor::new(data)).ok()
+    } else {
+        let file = File::open(path).ok()?;
+        reader.read_from_container(&mut BufReader::new(file)).ok()
+    }
+}
+
+fn exif_text(exif: &Exif, tag: Tag) -> Option<String> {
+    match exif.get_field(tag, In::PRIMARY)?.value {
+        Value::Ascii(ref values) => values
+            .first()
+            .map(|v| String::from_utf8_lossy(v).trim().to_string())
+            .filter(|v| !v.is_empty()),
+        _ => None,
+    }
+}
+
+/// Format a size in bytes with binary units, e.g. `1.5 MiB`
+pub(crate) fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn reads_file_details() {
+        let image = SimilarImage::test_image(PathBuf::from("test/rustA500.jpg"));
+        let metadata = Metadata::read(&image);
+        assert_eq!(metadata.format, "JPG");
+        assert!(metadata.modified.is_some());
+        let labels: Vec<_> = metadata.describe().into_iter().map(|(l, _)| l).collect();
+        assert_eq!(
+            labels,
+            [
+                "Dimensions",
+                "Size",
+                "Format",
+                "Modified",
+                "Camera",
+                "Taken"
+            ]
+        );
+    }
+
+    #[test]
+    fn formats_sizes() {
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KiB");
+        assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+    }
+}
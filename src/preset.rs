@@ -0,0 +1,122 @@
+use crate::hash_type::{HashType, InnerHashType};
+use crate::{Config, ScanError};
+use std::fmt;
+use std::str::FromStr;
+
+/// Named combinations of hashing method and hash length,
+/// so there is no need to know what either one does
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Preset {
+    /// Small mean hashes.  Good enough for exact and resized copies.
+    Fast,
+    /// The defaults
+    Balanced,
+    /// Large DCT hashes, which also notice color changes
+    Precise,
+}
+
+const PRESETS: [(Preset, &str, &str); 3] = [
+    (
+        Preset::Fast,
+        "fast",
+        "Quick, but only finds copies that look nearly the same",
+    ),
+    (
+        Preset::Balanced,
+        "balanced",
+        "Finds most edited copies at a reasonable speed",
+    ),
+    (
+        Preset::Precise,
+        "precise",
+        "Slow, but finds heavily edited and recolored copies",
+    ),
+];
+
+impl Preset {
+    /// Lists the presets and their descriptions
+    pub fn available_presets() -> Vec<(&'static str, &'static str)> {
+        PRESETS
+            .iter()
+            .map(|&(_, name, desc)| (name, desc))
+            .collect()
+    }
+
+    /// The hashing method this preset uses
+    pub fn method(self) -> HashType {
+        HashType::new(match self {
+            Preset::Fast => InnerHashType::Mean,
+            Preset::Balanced => InnerHashType::Gradient,
+            Preset::Precise => InnerHashType::DCT,
+        })
+    }
+
+    /// The hash length this preset uses
+    pub fn hash_size(self) -> u32 {
+        match self {
+            Preset::Fast => 8,
+            Preset::Balanced => 16,
+            Preset::Precise => 32,
+        }
+    }
+
+    /// Set the method and hash length of `config`
+    pub fn apply(self, config: &mut Config) {
+        config.method = self.method();
+        config.hash_size = self.hash_size();
+    }
+
+    /// The preset `config` hashes with, if its method and hash length are
+    /// those of one
+    pub fn of(config: &Config) -> Option<Preset> {
+        PRESETS
+            .iter()
+            .map(|&(preset, _, _)| preset)
+            .find(|p| p.method() == config.method && p.hash_size() == config.hash_size)
+    }
+}
+
+impl FromStr for Preset {
+    type Err = ScanError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        PRESETS
+            .iter()
+            .find(|&&(_, name, _)| name.eq_ignore_ascii_case(s))
+            .map(|&(preset, _, _)| preset)
+            .ok_or_else(|| ScanError::InvalidConfig {
+                reason: format!("Unknown preset {:?}, try fast, balanced or precise", s),
+            })
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = PRESETS.iter().find(|p| p.0 == *self).map_or("", |p| p.1);
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_are_valid_configs() {
+        for (name, _) in Preset::available_presets() {
+            let preset: Preset = name.parse().unwrap();
+            assert_eq!(preset.to_string(), name);
+            let mut config = Config::default();
+            preset.apply(&mut config);
+            config.validate().unwrap();
+            assert_eq!(Preset::of(&config), Some(preset));
+        }
+        assert_eq!(Preset::of(&Config::default()), Some(Preset::Balanced));
+        assert!("Precise".parse::<Preset>().is_ok());
+        assert!("slow".parse::<Preset>().is_err());
+
+        let mut config = Config::default();
+        config.hash_size = 0;
+        assert!(config.validate().is_err());
+        assert_eq!(Preset::of(&config), None);
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{Config, ScanError};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Optional config file in the root of a scanned directory
+const DIRECTORY_FILE: &str = ".img_dedup.toml";
+
+/// Settings that can be kept in a config file.
+/// Anything left out keeps the value it had before the file was read.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+struct Settings {
+    hash_type: Option<String>,
+    hash_length: Option<u32>,
+    threads: Option<usize>,
+    io_threads: Option<usize>,
+    ordered_reads: Option<bool>,
+    memory_budget: Option<u64>,
+    video: Option<bool>,
+    archives: Option<bool>,
+}
+
+/// A config file, e.g.
+///
+/// ```toml
+/// profile = "fuzzy"
+/// archives = true
+///
+/// [profiles.exact]
+/// hash_type = "Mean"
+/// hash_length = 8
+///
+/// [profiles.fuzzy]
+/// hash_type = "DCT"
+/// hash_length = 16
+/// ```
+///
+/// Top level settings apply to every scan, and a profile's settings
+/// override them when it is picked.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct ConfigFile {
+    /// Profile to use when none is given on the command line
+    profile: Option<String>,
+    #[serde(flatten)]
+    settings: Settings,
+    #[serde(default)]
+    profiles: BTreeMap<String, Settings>,
+}
+
+/// The config file in the user's config directory,
+/// e.g. `~/.config/img_dedup/config.toml`
+fn user_file() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("img_dedup").join("config.toml"))
+}
+
+/// Fill in every setting not `given` on the command line from the user's
+/// config file and then the one in the scanned directory
+pub(crate) fn load<G>(config: &mut Config, given: G) -> Result<(), ScanError>
+where
+    G: Fn(&str) -> bool,
+{
+    let paths = user_file()
+        .into_iter()
+        .chain(Some(config.directory.join(DIRECTORY_FILE)));
+    let mut files = Vec::new();
+    for path in paths {
+        if let Some(file) = read(&path)? {
+            files.push(file);
+        }
+    }
+    apply(config, &files, given)
+}
+
+/// Apply the files in order, so later ones win.  Profiles are applied after
+/// all the top level settings, as picking one is the more specific choice.
+fn apply<G>(config: &mut Config, files: &[ConfigFile], given: G) -> Result<(), ScanError>
+where
+    G: Fn(&str) -> bool,
+{
+    let profile = config
+        .profile
+        .clone()
+        .or_else(|| files.iter().rev().find_map(|f| f.profile.clone()));
+    if let Some(name) = &profile {
+        if !files.iter().any(|f| f.profiles.contains_key(name)) {
+            return Err(ScanError::InvalidConfig {
+                reason: format!("No profile named {:?} in the config files", name),
+            });
+        }
+    }
+
+    for file in files {
+        file.settings.apply(config, &given)?;
+    }
+    if let Some(name) = &profile {
+        for settings in files.iter().filter_map(|f| f.profiles.get(name)) {
+            settings.apply(config, &given)?;
+        }
+    }
+    config.profile = profile;
+    Ok(())
+}
+
+/// Save the settings of `config` as a profile in the user's config file,
+/// replacing any profile of the same name.  Comments in the file are lost.
+pub(crate) fn save(config: &Config, name: &str) -> Result<PathBuf, ScanError> {
+    let path = user_file().ok_or_else(|| ScanError::InvalidConfig {
+        reason: "No config directory to save profiles in".to_string(),
+    })?;
+    let mut file = read(&path)?.unwrap_or_default();
+    file.profiles
+        .insert(name.to_string(), Settings::from_config(config));
+    let text = toml::to_string(&file).map_err(|e| ScanError::InvalidConfig {
+        reason: format!("Cannot write profile {:?}: {}", name, e),
+    })?;
+
+    let write = |path: &Path| {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, text)
+    };
+    write(&path).map_err(|cause| ScanError::Io {
+        path: path.clone(),
+        cause,
+    })?;
+    Ok(path)
+}
+
+/// Read a config file, if there is one
+fn read(path: &Path) -> Result<Option<ConfigFile>, ScanError> {
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let text = fs::read_to_string(path).map_err(|cause| ScanError::Io {
+        path: path.to_path_buf(),
+        cause,
+    })?;
+    toml::from_str(&text)
+        .map(Some)
+        .map_err(|e| ScanError::InvalidConfig {
+            reason: format!("{:?}: {}", path, e),
+        })
+}
+
+impl Settings {
+    fn from_config(config: &Config) -> Settings {
+        Settings {
+            hash_type: Some(config.method.to_string()),
+            hash_length: Some(config.hash_size),
+            threads: Some(config.threads),
+            io_threads: Some(config.io_threads),
+            ordered_reads: Some(config.ordered_reads),
+            memory_budget: Some(config.memory_budget),
+            video: Some(config.video),
+            archives: Some(config.archives),
+        }
+    }
+
+    /// Copy the settings that are present, unless they were `given`.
+    /// `given` is asked about the names of the fields of `Config`.
+    fn apply<G>(&self, config: &mut Config, given: G) -> Result<(), ScanError>
+    where
+        G: Fn(&str) -> bool,
+    {
+        if let (Some(name), false) = (&self.hash_type, given("method")) {
+            config.method = name.parse().map_err(|e| ScanError::InvalidConfig {
+                reason: format!("{}", e),
+            })?;
+        }
+        set(&mut config.hash_size, self.hash_length, given("hash_size"));
+        set(&mut config.threads, self.threads, given("threads"));
+        set(&mut config.io_threads, self.io_threads, given("io_threads"));
+        set(
+            &mut config.ordered_reads,
+            self.ordered_reads,
+            given("ordered_reads"),
+        );
+        set(
+            &mut config.memory_budget,
+            self.memory_budget,
+            given("memory_budget"),
+        );
+        set(&mut config.video, self.video, given("video"));
+        set(&mut config.archives, self.archives, given("archives"));
+        Ok(())
+    }
+}
+
+fn set<T>(field: &mut T, value: Option<T>, given: bool) {
+    if let (Some(value), false) = (value, given) {
+        *field = value;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const USER_FILE: &str = r#"
+        profile = "fuzzy"
+        threads = 2
+
+        [profiles.exact]
+        hash_type = "Mean"
+        hash_length = 8
+
+        [profiles.fuzzy]
+        hash_type = "DCT"
+        hash_length = 32
+    "#;
+
+    const DIRECTORY_FILE: &str = r#"
+        profile = "exact"
+        archives = true
+        threads = 4
+    "#;
+
+    fn files() -> Vec<ConfigFile> {
+        vec![
+            toml::from_str(USER_FILE).unwrap(),
+            toml::from_str(DIRECTORY_FILE).unwrap(),
+        ]
+    }
+
+    #[test]
+    fn later_files_and_profiles_win() {
+        let mut config = Config::default();
+        apply(&mut config, &files(), |_| false).unwrap();
+        assert_eq!(config.profile, Some("exact".to_string()));
+        assert_eq!(config.method.to_string(), "Mean");
+        assert_eq!(config.hash_size, 8);
+        assert_eq!(config.threads, 4);
+        assert!(config.archives);
+    }
+
+    #[test]
+    fn command_line_wins() {
+        let mut config = Config::default();
+        config.profile = Some("fuzzy".to_string());
+        config.hash_size = 12;
+        apply(&mut config, &files(), |arg| arg == "hash_size").unwrap();
+        assert_eq!(config.method.to_string(), "DCT");
+        assert_eq!(config.hash_size, 12);
+    }
+
+    #[test]
+    fn unknown_profiles_are_errors() {
+        let mut config = Config::default();
+        config.profile = Some("missing".to_string());
+        assert!(apply(&mut config, &files(), |_| false).is_err());
+    }
+
+    #[test]
+    fn saved_settings_read_back() {
+        let mut file = ConfigFile::default();
+        let settings = Settings::from_config(&Config::default());
+        file.profiles
+            .insert("default".to_string(), settings.clone());
+        let read: ConfigFile = toml::from_str(&toml::to_string(&file).unwrap()).unwrap();
+        assert_eq!(read.profiles["default"], settings);
+    }
+}
@@ -0,0 +1,265 @@
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Stages of a scan, in the order they run
+#[derive(Copy, Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Phase {
+    /// Walking the directory for image files, while hashing those found
+    Discovering,
+    /// Reading and hashing each image
+    Hashing,
+    /// Comparing every pair of hashes
+    Comparing,
+    /// Ordering the pairs from most to least similar
+    Grouping,
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Phase::Discovering => "Searching for image files",
+            Phase::Hashing => "Hashing images",
+            Phase::Comparing => "Calculating differences",
+            Phase::Grouping => "Grouping matches",
+        };
+        f.write_str(text)
+    }
+}
+
+/// Status message from scanner
+#[derive(Clone, Debug)]
+pub enum StatusMsg {
+    /// The scanner has moved on to another phase
+    Phase(Phase),
+    /// How many image files have been found so far
+    Total(usize),
+    /// An image has been processed
+    ImageProcessed {
+        /// The file that was hashed, or failed to be
+        path: PathBuf,
+        /// How many bytes were read for it
+        bytes: u64,
+    },
+    /// An image could not be read or decoded, so is left out
+    Failed {
+        /// The file that was left out
+        path: PathBuf,
+        /// What went wrong
+        reason: String,
+    },
+    /// How many pairs of images will be compared
+    ComparisonTotal(u64),
+    /// This many more pairs have been compared
+    PairsCompared(u64),
+}
+
+/// Running totals built up from `StatusMsg`s, for showing progress
+#[derive(Clone, Debug)]
+pub struct Progress {
+    /// The phase the scanner is in
+    pub phase: Phase,
+    /// How many files will be hashed
+    pub total: usize,
+    /// How many files have been hashed so far
+    pub processed: usize,
+    /// How many bytes have been read so far
+    pub bytes: u64,
+    /// The file processed most recently
+    pub current: Option<PathBuf>,
+    /// The files that could not be hashed, and why
+    pub failed: Vec<(PathBuf, String)>,
+    /// How many pairs will be compared
+    pub pairs_total: u64,
+    /// How many pairs have been compared so far
+    pub pairs_compared: u64,
+    /// When the scan began, as hashing starts alongside discovery
+    started: Instant,
+}
+
+impl Progress {
+    /// Progress of a scan that has not started yet
+    pub fn new() -> Progress {
+        Progress {
+            phase: Phase::Discovering,
+            total: 0,
+            processed: 0,
+            bytes: 0,
+            current: None,
+            failed: Vec::new(),
+            pairs_total: 0,
+            pairs_compared: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Take a status message into account
+    pub fn update(&mut self, msg: StatusMsg) {
+        match msg {
+            StatusMsg::Phase(phase) => self.phase = phase,
+            StatusMsg::Total(total) => self.total = total,
+            StatusMsg::ImageProcessed { path, bytes } => {
+                self.processed += 1;
+                self.bytes += bytes;
+                self.current = Some(path);
+            }
+            StatusMsg::Failed { path, reason } => self.failed.push((path, reason)),
+            StatusMsg::ComparisonTotal(total) => self.pairs_total = total,
+            StatusMsg::PairsCompared(pairs) => self.pairs_compared += pairs,
+        }
+    }
+
+    /// How much of the hashing is done, between 0 and 1
+    pub fn fraction(&self) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        (self.processed as f64 / self.total as f64).min(1.0)
+    }
+
+    /// How much of the comparison is done, between 0 and 1
+    pub fn comparison_fraction(&self) -> f64 {
+        if self.pairs_total == 0 {
+            return 0.0;
+        }
+        (self.pairs_compared as f64 / self.pairs_total as f64).min(1.0)
+    }
+
+    /// Images hashed per second since the scan began
+    pub fn images_per_second(&self) -> f64 {
+        self.processed as f64 / self.elapsed_seconds()
+    }
+
+    /// Bytes read per second since the scan began
+    pub fn bytes_per_second(&self) -> f64 {
+        self.bytes as f64 / self.elapsed_seconds()
+    }
+
+    /// Estimate how long the rest of the hashing will take.
+    /// There is no estimate until the first image is done,
+    /// or while files are still being found.
+    pub fn time_left(&self) -> Option<Duration> {
+        let rate = self.images_per_second();
+        if self.phase == Phase::Discovering || self.processed == 0 || rate <= 0.0 {
+            return None;
+        }
+        let remaining = self.total.saturating_sub(self.processed) as f64;
+        Some(Duration::from_millis((remaining / rate * 1000.0) as u64))
+    }
+
+    fn elapsed_seconds(&self) -> f64 {
+        let elapsed = self.started.elapsed();
+        // Avoid dividing by zero right as the scan starts
+        (elapsed.as_secs() as f64 + f64::from(elapsed.subsec_millis()) / 1000.0).max(0.001)
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Progress {
+        Progress::new()
+    }
+}
+
+/// A one line summary, e.g.
+/// `Hashing images 45 / 100, 12.3 images/s, 4.1 MiB/s, 0:42 left`
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.phase)?;
+        if self.phase == Phase::Comparing {
+            return write!(f, " {} / {} pairs", self.pairs_compared, self.pairs_total);
+        }
+        if self.phase == Phase::Discovering {
+            return write!(f, ", {} found, {} hashed", self.total, self.processed);
+        }
+        if self.phase != Phase::Hashing {
+            return Ok(());
+        }
+        write!(
+            f,
+            " {} / {}, {:.1} images/s, {:.1} MiB/s",
+            self.processed,
+            self.total,
+            self.images_per_second(),
+            self.bytes_per_second() / (1024.0 * 1024.0)
+        )?;
+        if let Some(left) = self.time_left() {
+            write!(f, ", {} left", format_duration(left))?;
+        }
+        if !self.failed.is_empty() {
+            write!(f, ", {} failed", self.failed.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// Format a duration as `m:ss`, or `h:mm:ss` once it reaches an hour
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_time_left_from_rate() {
+        let mut progress = Progress::new();
+        progress.update(StatusMsg::Total(10));
+        progress.update(StatusMsg::Phase(Phase::Hashing));
+        assert_eq!(progress.time_left(), None);
+        for i in 0..5 {
+            progress.update(StatusMsg::ImageProcessed {
+                path: PathBuf::from(format!("{}.jpg", i)),
+                bytes: 100,
+            });
+        }
+        progress.started = Instant::now() - Duration::from_secs(10);
+
+        assert_eq!(progress.fraction(), 0.5);
+        assert_eq!(progress.bytes, 500);
+        assert_eq!(progress.current, Some(PathBuf::from("4.jpg")));
+        assert_eq!(progress.time_left().unwrap().as_secs(), 10);
+    }
+
+    #[test]
+    fn counts_compared_pairs() {
+        let mut progress = Progress::new();
+        progress.update(StatusMsg::Phase(Phase::Comparing));
+        progress.update(StatusMsg::ComparisonTotal(40));
+        progress.update(StatusMsg::PairsCompared(10));
+        progress.update(StatusMsg::PairsCompared(20));
+        assert_eq!(progress.comparison_fraction(), 0.75);
+        assert_eq!(
+            progress.to_string(),
+            "Calculating differences 30 / 40 pairs"
+        );
+    }
+
+    #[test]
+    fn remembers_failures() {
+        let mut progress = Progress::new();
+        progress.update(StatusMsg::Phase(Phase::Hashing));
+        let failed = StatusMsg::Failed {
+            path: PathBuf::from("broken.jpg"),
+            reason: "Format error".to_string(),
+        };
+        progress.update(failed);
+        assert_eq!(
+            progress.failed,
+            vec![(PathBuf::from("broken.jpg"), "Format error".to_string())]
+        );
+        assert!(progress.to_string().ends_with(", 1 failed"));
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(Duration::from_secs(42)), "0:42");
+        assert_eq!(format_duration(Duration::from_secs(3_725)), "1:02:05");
+    }
+}
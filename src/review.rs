@@ -0,0 +1,484 @@
+use crate::{session, Classification, ScanError, SimilarImage, SimilarPair, Summary};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// What the user chose to do with a pair
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq, Serialize)]
+pub enum Decision {
+    /// Keep the left image and delete the right one
+    KeepLeft,
+    /// Keep the right image and delete the left one
+    KeepRight,
+    /// Keep both images
+    KeepBoth,
+    /// Leave the pair for later
+    Skip,
+    /// The images are not duplicates, and should not be paired again
+    Ignore,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Decision::KeepLeft => "Keep left",
+            Decision::KeepRight => "Keep right",
+            Decision::KeepBoth => "Keep both",
+            Decision::Skip => "Skipped",
+            Decision::Ignore => "Not duplicates",
+        };
+        f.write_str(text)
+    }
+}
+
+/// Ways the list of pairs can be ordered
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SortOrder {
+    /// Most similar first
+    Similarity,
+    /// Largest files first
+    Size,
+    /// Alphabetically by the folders the images are in
+    Folder,
+}
+
+const SORT_ORDERS: [(SortOrder, &str, &str); 3] = [
+    (SortOrder::Similarity, "similarity", "Most similar first"),
+    (SortOrder::Size, "size", "Largest files first"),
+    (SortOrder::Folder, "folder", "By folder name"),
+];
+
+impl SortOrder {
+    /// Lists the sort orders and their descriptions
+    pub fn available_orders() -> Vec<(&'static str, &'static str)> {
+        SORT_ORDERS
+            .iter()
+            .map(|&(_, name, desc)| (name, desc))
+            .collect()
+    }
+}
+
+impl FromStr for SortOrder {
+    type Err = ScanError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        SORT_ORDERS
+            .iter()
+            .find(|&&(_, name, _)| name.eq_ignore_ascii_case(s))
+            .map(|&(order, _, _)| order)
+            .ok_or_else(|| ScanError::InvalidConfig {
+                reason: format!("Unknown sort order {:?}", s),
+            })
+    }
+}
+
+/// Which pairs to list.  The default lets every pair through.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Filter {
+    /// Text that the folder of either image must contain, ignoring case
+    pub folder: String,
+    /// Lowest similarity to list
+    pub min_similarity: usize,
+    /// Size in bytes that the larger file of the pair must reach
+    pub min_size: u64,
+}
+
+impl Filter {
+    /// Whether `pair` should be listed
+    pub fn matches(&self, pair: &SimilarPair) -> bool {
+        let folder = self.folder.to_lowercase();
+        let in_folder = |image: &SimilarImage| {
+            image.path.parent().map_or(false, |p| {
+                p.to_string_lossy().to_lowercase().contains(&folder)
+            })
+        };
+        pair.similarity >= self.min_similarity
+            && pair_size(pair) >= self.min_size
+            && (folder.is_empty() || in_folder(&pair.left) || in_folder(&pair.right))
+    }
+}
+
+/// The size of the larger file of a pair
+fn pair_size(pair: &SimilarPair) -> u64 {
+    pair.left.size().max(pair.right.size())
+}
+
+/// Pairs being reviewed, most similar first, along with the decisions made
+/// so far.  Any pair can be revisited, and decisions can be undone.
+#[derive(Debug, Default)]
+pub struct Review {
+    pairs: Vec<SimilarPair>,
+    decisions: Vec<Option<Decision>>,
+    /// Index of the pair being reviewed, `pairs.len()` once all are decided
+    cursor: usize,
+    /// Each decision made, with the pairs it was made about and what they
+    /// were decided as before, for undoing
+    history: Vec<(Decision, Vec<(usize, Option<Decision>)>)>,
+}
+
+impl Review {
+    /// Start reviewing `pairs` from the most similar
+    pub fn new(pairs: BinaryHeap<SimilarPair>) -> Review {
+        let pairs: Vec<_> = pairs.into_sorted_vec().into_iter().rev().collect();
+        Review {
+            decisions: vec![None; pairs.len()],
+            pairs,
+            cursor: 0,
+            history: Vec::new(),
+        }
+    }
+
+    /// Open a review saved with `save`, to carry on where it was left
+    pub fn open(path: &Path) -> Result<Review, ScanError> {
+        session::load(path)
+    }
+
+    /// Save the pairs and the decisions made about them
+    pub fn save(&self, path: &Path) -> Result<(), ScanError> {
+        session::save(self, path)
+    }
+
+    /// A review with some decisions already made.  There is nothing to undo.
+    pub(crate) fn resume(
+        pairs: Vec<SimilarPair>,
+        decisions: Vec<Option<Decision>>,
+        position: usize,
+    ) -> Review {
+        Review {
+            cursor: position.min(pairs.len()),
+            pairs,
+            decisions,
+            history: Vec::new(),
+        }
+    }
+
+    /// The pair being reviewed, or `None` once they have all been decided
+    pub fn current(&self) -> Option<&SimilarPair> {
+        self.pairs.get(self.cursor)
+    }
+
+    /// Index of the pair being reviewed
+    pub fn position(&self) -> usize {
+        self.cursor
+    }
+
+    /// How many pairs there are in total
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Whether there are no pairs at all
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// The pair at `index` and what was decided about it
+    pub fn get(&self, index: usize) -> Option<(&SimilarPair, Option<Decision>)> {
+        Some((self.pairs.get(index)?, self.decisions[index]))
+    }
+
+    /// Review the pair at `index` next, whether or not it was decided on
+    pub fn go_to(&mut self, index: usize) {
+        if index < self.pairs.len() {
+            self.cursor = index;
+        }
+    }
+
+    /// Go back to the pair before the current one
+    pub fn previous(&mut self) {
+        self.cursor = self.cursor.saturating_sub(1);
+    }
+
+    /// Decide on the current pair and move on to the next undecided one
+    pub fn decide(&mut self, decision: Decision) {
+        let cursor = self.cursor;
+        self.decide_all(&[cursor], decision);
+    }
+
+    /// Make the same decision about several pairs at once, such as a group
+    /// from `group`, and move on to the next undecided pair.  Undoing it
+    /// undoes the decision about all of them.
+    pub fn decide_all(&mut self, indices: &[usize], decision: Decision) {
+        let len = self.pairs.len();
+        let decisions = &mut self.decisions;
+        let before: Vec<_> = indices
+            .iter()
+            .filter(|&&i| i < len)
+            .map(|&i| (i, decisions[i].replace(decision)))
+            .collect();
+        if before.is_empty() {
+            return;
+        }
+        self.history.push((decision, before));
+        let next = self.upcoming().next();
+        self.cursor = next.unwrap_or(len);
+    }
+
+    /// Drop every pair with one of the `deleted` files in it, once they
+    /// are gone.  Decisions made before can no longer be undone.
+    pub fn forget(&mut self, deleted: &[PathBuf]) {
+        let deleted: HashSet<&Path> = deleted.iter().map(PathBuf::as_path).collect();
+        let gone = |pair: &SimilarPair| {
+            deleted.contains(pair.left.path.as_path())
+                || deleted.contains(pair.right.path.as_path())
+        };
+        let (pairs, decisions) = self
+            .pairs
+            .drain(..)
+            .zip(self.decisions.drain(..))
+            .filter(|(pair, _)| !gone(pair))
+            .unzip();
+        self.pairs = pairs;
+        self.decisions = decisions;
+        self.history.clear();
+        self.cursor = self
+            .decisions
+            .iter()
+            .position(Option::is_none)
+            .unwrap_or_else(|| self.pairs.len());
+    }
+
+    /// Indices of the undecided pairs after the current one, in the order
+    /// they will be reviewed as decisions are made, wrapping around
+    pub fn upcoming(&self) -> impl Iterator<Item = usize> + '_ {
+        let len = self.pairs.len();
+        (self.cursor + 1..len)
+            .chain(0..self.cursor.min(len))
+            .filter(move |&i| self.decisions[i].is_none())
+    }
+
+    /// Go back to the last pair decided on, restoring what it was decided
+    /// as before.  Gives the decision that was undone along with the pairs
+    /// it was made about, if there was one.
+    pub fn undo(&mut self) -> Option<(Decision, Vec<usize>)> {
+        let (decision, before) = self.history.pop()?;
+        self.cursor = before[0].0;
+        let indices = before
+            .into_iter()
+            .map(|(index, decision)| {
+                self.decisions[index] = decision;
+                index
+            })
+            .collect();
+        Some((decision, indices))
+    }
+
+    /// Indices of the pair at `index` and the close matches among its
+    /// images and every image closely matched with both of them, such as a
+    /// burst of shots.  Every image is paired with every other, so pairs
+    /// only `Edited` from one another are not followed.
+    pub fn group(&self, index: usize) -> Vec<usize> {
+        let pair = match self.pairs.get(index) {
+            Some(pair) => pair,
+            None => return Vec::new(),
+        };
+        let close = |p: &&SimilarPair| p.classification != Classification::Edited;
+        let partners = |image: &SimilarImage| -> HashSet<&Path> {
+            self.pairs
+                .iter()
+                .filter(close)
+                .filter_map(|p| {
+                    if *p.left == *image {
+                        Some(p.right.path.as_path())
+                    } else if *p.right == *image {
+                        Some(p.left.path.as_path())
+                    } else {
+                        None
+                    }
+                })
+                .collect()
+        };
+        let mut members: HashSet<&Path> = partners(&pair.left)
+            .intersection(&partners(&pair.right))
+            .cloned()
+            .collect();
+        members.insert(&pair.left.path);
+        members.insert(&pair.right.path);
+        (0..self.pairs.len())
+            .filter(|&i| {
+                let other = &self.pairs[i];
+                i == index
+                    || close(&other)
+                        && members.contains(other.left.path.as_path())
+                        && members.contains(other.right.path.as_path())
+            })
+            .collect()
+    }
+
+    /// How many pairs have not been decided on yet
+    pub fn remaining(&self) -> usize {
+        self.decisions.iter().filter(|d| d.is_none()).count()
+    }
+
+    /// The decisions made so far, most similar pair first
+    pub fn decisions(&self) -> impl Iterator<Item = (&SimilarPair, Decision)> {
+        self.pairs
+            .iter()
+            .zip(&self.decisions)
+            .filter_map(|(pair, decision)| decision.map(|d| (pair, d)))
+    }
+
+    /// What the decisions so far add up to, and the files they would delete
+    pub fn summary(&self) -> Summary {
+        Summary::new(self.decisions(), self.remaining())
+    }
+
+    /// Indices of the pairs let through by `filter`, in `order`
+    pub fn list(&self, filter: &Filter, order: SortOrder) -> Vec<usize> {
+        let mut indices: Vec<usize> = (0..self.pairs.len())
+            .filter(|&i| filter.matches(&self.pairs[i]))
+            .collect();
+        let pairs = &self.pairs;
+        match order {
+            // Already in this order
+            SortOrder::Similarity => {}
+            SortOrder::Size => indices.sort_by_key(|&i| Reverse(pair_size(&pairs[i]))),
+            SortOrder::Folder => indices
+                .sort_by_key(|&i| (pairs[i].left.path.parent(), pairs[i].right.path.parent())),
+        }
+        indices
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use std::sync::Arc;
+
+    fn pair_in(folder: &str, similarity: usize) -> SimilarPair {
+        let image =
+            |name: String| Arc::new(SimilarImage::test_image(PathBuf::from(folder).join(name)));
+        SimilarPair::new(
+            similarity,
+            image(format!("{}a.jpg", similarity)),
+            image(format!("{}b.jpg", similarity)),
+            Classification::Edited,
+        )
+    }
+
+    fn pair(similarity: usize) -> SimilarPair {
+        pair_in("photos", similarity)
+    }
+
+    #[test]
+    fn undo_returns_to_the_last_pair() {
+        let mut review = Review::new(vec![pair(1), pair(3), pair(2)].into_iter().collect());
+        assert_eq!(review.current().unwrap().similarity, 3);
+        review.decide(Decision::KeepLeft);
+        review.decide(Decision::Skip);
+        assert_eq!(review.current().unwrap().similarity, 1);
+
+        assert_eq!(review.undo(), Some((Decision::Skip, vec![1])));
+        assert_eq!(review.current().unwrap().similarity, 2);
+        assert_eq!(review.remaining(), 2);
+        review.decide(Decision::KeepBoth);
+        review.decide(Decision::KeepRight);
+        assert!(review.current().is_none());
+
+        let decisions: Vec<_> = review.decisions().map(|(p, d)| (p.similarity, d)).collect();
+        assert_eq!(
+            decisions,
+            vec![
+                (3, Decision::KeepLeft),
+                (2, Decision::KeepBoth),
+                (1, Decision::KeepRight)
+            ]
+        );
+        assert_eq!(review.undo(), Some((Decision::KeepRight, vec![2])));
+        assert_eq!(review.current().unwrap().similarity, 1);
+    }
+
+    #[test]
+    fn deleted_files_are_forgotten() {
+        let mut review = Review::new(vec![pair(1), pair(2), pair(3)].into_iter().collect());
+        review.decide(Decision::KeepLeft);
+        review.forget(&[review.get(0).unwrap().0.right.path.clone()]);
+        assert_eq!(review.len(), 2);
+        assert_eq!(review.current().unwrap().similarity, 2);
+        assert_eq!(review.remaining(), 2);
+        assert_eq!(review.undo(), None);
+        assert!(review.summary().deletions.is_empty());
+    }
+
+    #[test]
+    fn decided_pairs_can_be_revisited() {
+        let mut review = Review::new(vec![pair(1), pair(2), pair(3)].into_iter().collect());
+        review.go_to(1);
+        assert_eq!(review.upcoming().collect::<Vec<_>>(), vec![2, 0]);
+        review.decide(Decision::KeepLeft);
+        // Moves on to the next undecided pair, wrapping around
+        assert_eq!(review.position(), 2);
+        review.decide(Decision::KeepLeft);
+        assert_eq!(review.position(), 0);
+        review.previous();
+        assert_eq!(review.position(), 0);
+
+        review.go_to(1);
+        review.decide(Decision::KeepRight);
+        assert_eq!(review.get(1).unwrap().1, Some(Decision::KeepRight));
+        assert_eq!(review.undo(), Some((Decision::KeepRight, vec![1])));
+        assert_eq!(review.get(1).unwrap().1, Some(Decision::KeepLeft));
+        assert_eq!(review.remaining(), 1);
+    }
+
+    #[test]
+    fn lists_matching_pairs_in_order() {
+        let pairs = vec![
+            pair_in("b/holiday", 3),
+            pair_in("a", 5),
+            pair_in("Holiday", 4),
+        ];
+        let review = Review::new(pairs.into_iter().collect());
+        let mut filter = Filter::default();
+        assert_eq!(review.list(&filter, SortOrder::Similarity), vec![0, 1, 2]);
+        assert_eq!(review.list(&filter, SortOrder::Folder), vec![1, 0, 2]);
+
+        filter.folder = "holiday".to_string();
+        assert_eq!(review.list(&filter, SortOrder::Folder), vec![1, 2]);
+        filter.min_similarity = 4;
+        assert_eq!(review.list(&filter, SortOrder::Similarity), vec![1]);
+        filter.min_size = 1;
+        assert!(review.list(&filter, SortOrder::Size).is_empty());
+        assert_eq!("Size".parse::<SortOrder>().unwrap(), SortOrder::Size);
+    }
+
+    #[test]
+    fn groups_are_decided_together() {
+        let image = |name: &str| Arc::new(SimilarImage::test_image(PathBuf::from(name)));
+        let (a, b, c, d) = (image("a"), image("b"), image("c"), image("d"));
+        let unrelated = image("z");
+        let pair = |similarity, x: &Arc<SimilarImage>, y: &Arc<SimilarImage>| {
+            SimilarPair::new(similarity, x.clone(), y.clone(), Classification::Resized)
+        };
+        let distant = |similarity, x: &Arc<SimilarImage>, y: &Arc<SimilarImage>| {
+            SimilarPair::new(similarity, x.clone(), y.clone(), Classification::Edited)
+        };
+        // A burst of a, b and c, with d only like a, and z matched with
+        // everything as every image is
+        let pairs = vec![
+            pair(9, &a, &b),
+            pair(8, &b, &c),
+            pair(7, &a, &c),
+            pair(6, &a, &d),
+            distant(5, &a, &unrelated),
+            distant(4, &b, &unrelated),
+            distant(3, &c, &unrelated),
+            distant(2, &d, &unrelated),
+        ];
+        let mut review = Review::new(pairs.into_iter().collect());
+        let group = review.group(0);
+        assert_eq!(group, vec![0, 1, 2]);
+        // A distant pair is only grouped with itself
+        assert_eq!(review.group(4), vec![4]);
+
+        review.decide_all(&group, Decision::Ignore);
+        assert_eq!(review.position(), 3);
+        assert_eq!(review.remaining(), 5);
+        assert_eq!(review.undo(), Some((Decision::Ignore, group)));
+        assert_eq!(review.position(), 0);
+        assert_eq!(review.remaining(), 8);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::{Classification, Decision, Review, ScanError, SimilarImage, SimilarPair};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Changed whenever older sessions can no longer be read
+const VERSION: u32 = 4;
+
+/// A review saved to disk.  Each image is stored once, and the pairs
+/// refer to images by their index.
+#[derive(Debug, Deserialize, Serialize)]
+struct SessionFile {
+    version: u32,
+    images: Vec<SimilarImage>,
+    pairs: Vec<PairRecord>,
+    /// Index of the pair that was being reviewed
+    position: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+struct PairRecord {
+    similarity: usize,
+    left: usize,
+    right: usize,
+    classification: Classification,
+    decision: Option<Decision>,
+}
+
+/// Write `review` to `path`.  The file is replaced in one step, so an
+/// interrupted save leaves the previous session intact.
+pub(crate) fn save(review: &Review, path: &Path) -> Result<(), ScanError> {
+    let mut images = Vec::new();
+    let mut indices: HashMap<PathBuf, usize> = HashMap::new();
+    let mut index_of = |image: &Arc<SimilarImage>| {
+        *indices.entry(image.path.clone()).or_insert_with(|| {
+            images.push(SimilarImage::clone(image));
+            images.len() - 1
+        })
+    };
+    let pairs = (0..review.len())
+        .filter_map(|i| review.get(i))
+        .map(|(pair, decision)| PairRecord {
+            similarity: pair.similarity,
+            left: index_of(&pair.left),
+            right: index_of(&pair.right),
+            classification: pair.classification,
+            decision,
+        })
+        .collect();
+    let file = SessionFile {
+        version: VERSION,
+        images,
+        pairs,
+        position: review.position(),
+    };
+
+    let io_error = |cause| ScanError::Io {
+        path: path.to_path_buf(),
+        cause,
+    };
+    let text = serde_json::to_string(&file).map_err(|e| io_error(e.into()))?;
+    replace_file(path, text.as_bytes()).map_err(io_error)
+}
+
+/// Read a review written by `save`
+pub(crate) fn load(path: &Path) -> Result<Review, ScanError> {
+    let invalid = |reason: String| ScanError::InvalidSession {
+        path: path.to_path_buf(),
+        reason,
+    };
+    let text = fs::read_to_string(path).map_err(|cause| ScanError::Io {
+        path: path.to_path_buf(),
+        cause,
+    })?;
+    let file: SessionFile = serde_json::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+    if file.version != VERSION {
+        return Err(invalid(format!(
+            "Saved by an incompatible version ({})",
+            file.version
+        )));
+    }
+
+    let images: Vec<_> = file.images.into_iter().map(Arc::new).collect();
+    let image = |index: usize| {
+        images
+            .get(index)
+            .cloned()
+            .ok_or_else(|| invalid(format!("No image number {}", index)))
+    };
+    let mut pairs = Vec::with_capacity(file.pairs.len());
+    let mut decisions = Vec::with_capacity(file.pairs.len());
+    for record in file.pairs {
+        pairs.push(SimilarPair::new(
+            record.similarity,
+            image(record.left)?,
+            image(record.right)?,
+            record.classification,
+        ));
+        decisions.push(record.decision);
+    }
+    Ok(Review::resume(pairs, decisions, file.position))
+}
+
+/// Write `contents` next to `path` and then move them over it,
+/// so that readers never see a half written file.  Only the user can
+/// read the file, as it names their files.
+pub(crate) fn replace_file(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(".partial");
+    let partial = path.with_file_name(name);
+    let mut file = private_file(&partial)?;
+    file.write_all(contents)?;
+    drop(file);
+    fs::rename(&partial, path)
+}
+
+#[cfg(unix)]
+fn private_file(path: &Path) -> io::Result<File> {
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+    let file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // The mode is only used for new files, so set it on an old one too
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    Ok(file)
+}
+
+#[cfg(not(unix))]
+fn private_file(path: &Path) -> io::Result<File> {
+    File::create(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BinaryHeap;
+
+    #[test]
+    fn sessions_read_back() {
+        let image = |name: &str| Arc::new(SimilarImage::test_image(PathBuf::from(name)));
+        let (a, b, c) = (image("a.jpg"), image("b.jpg"), image("c.jpg"));
+        let mut pairs = BinaryHeap::new();
+        pairs.push(SimilarPair::new(
+            9,
+            a.clone(),
+            b.clone(),
+            Classification::Exact,
+        ));
+        pairs.push(SimilarPair::new(5, a, c, Classification::Edited));
+        let mut review = Review::new(pairs);
+        review.decide(Decision::KeepLeft);
+
+        let path =
+            std::env::temp_dir().join(format!("img_dedup_session_{}.json", std::process::id()));
+        review.save(&path).unwrap();
+        let read = Review::open(&path);
+        fs::remove_file(&path).unwrap();
+        let read = read.unwrap();
+
+        assert_eq!(read.len(), 2);
+        assert_eq!(read.position(), 1);
+        let (first, decision) = read.get(0).unwrap();
+        assert_eq!((first.similarity, decision), (9, Some(Decision::KeepLeft)));
+        assert_eq!(first.right.path, PathBuf::from("b.jpg"));
+        let (second, decision) = read.get(1).unwrap();
+        assert_eq!(
+            (second.classification, decision),
+            (Classification::Edited, None)
+        );
+        // Images shared between pairs are only stored once
+        assert!(Arc::ptr_eq(&first.left, &second.left));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn replaced_files_are_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("img_dedup_private_{}", std::process::id()));
+        fs::write(&path, b"old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        replace_file(&path, b"new").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        let contents = fs::read(&path);
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(contents.unwrap(), b"new");
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
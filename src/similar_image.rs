@@ -1,39 +1,95 @@
-use image::{DynamicImage, GenericImage};
-use std::cell::RefCell;
+use crate::color::ColorSignature;
+use crate::{decode, video, Digest};
+use image::DynamicImage;
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
+use std::fmt;
 use std::path::PathBuf;
-use std::rc::Rc;
+use std::sync::Arc;
+
+/// Chroma distance above which two otherwise similar images are
+/// considered recolored variants of each other
+const RECOLOR_THRESHOLD: u32 = 8;
 
 /// Represents an image and attributes that may be used for comparison
 /// to choose the favored of two duplicates
-#[derive(Clone, Debug, Eq, Ord)]
+#[derive(Clone, Debug, Deserialize, Eq, Ord, Serialize)]
 pub struct SimilarImage {
     /// Path to the image
     pub path: PathBuf,
     width: u32,
     height: u32,
+    size: u64,
+    color: ColorSignature,
+    frames: usize,
+    digest: Digest,
 }
 
 impl SimilarImage {
-    /// Create a new `SimilarImage`.
-    /// This function will scan the image for attributes like dimensions
-    pub fn new(path: PathBuf, image: &DynamicImage) -> SimilarImage {
-        let (width, height) = image.dimensions();
+    /// Create a new `SimilarImage` from the decoded frames of an image,
+    /// which may have been shrunk from its original `dimensions`, and the
+    /// `size` of its file in bytes.
+    /// This function will scan the first frame for attributes like color
+    pub fn new(
+        path: PathBuf,
+        size: u64,
+        dimensions: (u32, u32),
+        frames: &[DynamicImage],
+        digest: Digest,
+    ) -> SimilarImage {
+        let (width, height) = dimensions;
         SimilarImage {
             path,
             width,
             height,
+            size,
+            color: frames
+                .first()
+                .map_or_else(ColorSignature::default, ColorSignature::new),
+            frames: frames.len(),
+            digest,
         }
     }
 
-    /// Do not use outside of testing
+    /// Do not use outside of testing.  The digest is taken from the path,
+    /// so that differently named test images count as different files.
     pub fn test_image(path: PathBuf) -> SimilarImage {
+        let digest = Digest::of(path.to_string_lossy().as_bytes());
         SimilarImage {
             path,
             width: 0,
             height: 0,
+            size: 0,
+            color: ColorSignature::default(),
+            frames: 1,
+            digest,
         }
     }
+
+    /// Width and height of the image in pixels
+    pub fn dimensions(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    /// Size of the file in bytes
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Digest of the contents of the file
+    pub fn digest(&self) -> Digest {
+        self.digest
+    }
+
+    /// Whether more than one frame of this image was hashed
+    pub fn is_animated(&self) -> bool {
+        self.frames > 1
+    }
+
+    /// Whether this is a video, which only ffmpeg can decode
+    pub fn is_video(&self) -> bool {
+        video::is_video(&decode::extension(&self.path))
+    }
 }
 
 impl PartialEq for SimilarImage {
@@ -61,9 +117,11 @@ pub struct SimilarPair {
     // expect the user to delete, based on size of the file, length of
     // path, etc.
     /// Image least expected to be deleted
-    pub left: Rc<RefCell<SimilarImage>>,
+    pub left: Arc<SimilarImage>,
     /// Image most expected to be deleted
-    pub right: Rc<RefCell<SimilarImage>>,
+    pub right: Arc<SimilarImage>,
+    /// How the two images are thought to be related
+    pub classification: Classification,
 }
 
 impl SimilarPair {
@@ -71,13 +129,68 @@ impl SimilarPair {
     /// TODO: Does this need to be public?  Maybe look at crate visibility
     pub fn new(
         similarity: usize,
-        left: Rc<RefCell<SimilarImage>>,
-        right: Rc<RefCell<SimilarImage>>,
+        left: Arc<SimilarImage>,
+        right: Arc<SimilarImage>,
+        classification: Classification,
     ) -> SimilarPair {
         SimilarPair {
             similarity,
             left,
             right,
+            classification,
         }
     }
 }
+
+/// The kind of duplicate a `SimilarPair` is
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum Classification {
+    /// Same hash, dimensions and file size
+    Exact,
+    /// Same picture at different dimensions
+    Resized,
+    /// Same picture and dimensions, but encoded differently
+    Recompressed,
+    /// Same picture with different colors, e.g. converted to grayscale or sepia
+    Recolored,
+    /// Anything else that hashed similarly enough to be paired
+    Edited,
+}
+
+impl Classification {
+    /// Classify a pair of images given the number of matching bits
+    /// out of the total length of their hashes
+    pub fn classify(
+        similarity: usize,
+        hash_len: usize,
+        left: &SimilarImage,
+        right: &SimilarImage,
+    ) -> Classification {
+        // Resizing and recompressing flip a few bits, so allow 10% slack
+        let near = similarity * 10 >= hash_len * 9;
+        if !near {
+            Classification::Edited
+        } else if left.color.distance(&right.color) > RECOLOR_THRESHOLD {
+            Classification::Recolored
+        } else if (left.width, left.height) != (right.width, right.height) {
+            Classification::Resized
+        } else if similarity < hash_len || left.size != right.size {
+            Classification::Recompressed
+        } else {
+            Classification::Exact
+        }
+    }
+}
+
+impl fmt::Display for Classification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            Classification::Exact => "Exact duplicate",
+            Classification::Resized => "Resized",
+            Classification::Recompressed => "Recompressed",
+            Classification::Recolored => "Recolored",
+            Classification::Edited => "Edited",
+        };
+        write!(f, "{}", name)
+    }
+}
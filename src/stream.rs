@@ -0,0 +1,63 @@
+use crate::{scan_pairs, Config, IgnoreList, ScanError, SimilarPair, StatusMsg};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+/// Pairs of similar images, yielded while the scan is still running.
+///
+/// Iterating ends when the scan does.  Call `finish` afterwards to find out
+/// whether it succeeded, or earlier to cancel it.
+#[derive(Debug)]
+pub struct PairStream {
+    pairs: Receiver<SimilarPair>,
+    scan: JoinHandle<Result<(), ScanError>>,
+}
+
+/// Scan the configured directory on a background thread,
+/// streaming pairs out as soon as they are found.
+/// Pairs in `ignored` are left out.
+pub fn stream_pairs(config: Config, ignored: IgnoreList, sender: Sender<StatusMsg>) -> PairStream {
+    let (pair_sender, pairs) = channel();
+    let scan = thread::spawn(move || {
+        // Stop once nobody is listening any more
+        scan_pairs(&config, &ignored, sender, |pair| {
+            pair_sender.send(pair).is_ok()
+        })
+    });
+    PairStream { pairs, scan }
+}
+
+impl PairStream {
+    /// Wait for the scan to end.  If it is still running, it is cancelled
+    /// and pairs not yet taken are dropped.
+    pub fn finish(self) -> Result<(), ScanError> {
+        drop(self.pairs);
+        self.scan.join().unwrap()
+    }
+}
+
+impl Iterator for PairStream {
+    type Item = SimilarPair;
+
+    fn next(&mut self) -> Option<SimilarPair> {
+        self.pairs.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn streams_every_pair() {
+        let (sender, _receiver) = channel();
+        let mut config = Config::default();
+        config.directory = PathBuf::from("./test");
+        let ignored = IgnoreList::default();
+        let mut stream = stream_pairs(config.clone(), ignored.clone(), sender.clone());
+        let streamed = stream.by_ref().count();
+        stream.finish().unwrap();
+        let scanned = crate::scan(&config, &ignored, sender).unwrap();
+        assert_eq!(streamed, scanned.len());
+    }
+}
@@ -0,0 +1,199 @@
+use crate::metadata::format_size;
+use crate::{archive, decode, Decision, ScanError, SimilarImage, SimilarPair};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// What the decisions of a review add up to, and the files they would delete
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Summary {
+    /// How many pairs were decided on
+    pub reviewed: usize,
+    /// How many pairs were not decided on
+    pub undecided: usize,
+    /// Files to delete, each with the file it is a duplicate of
+    pub deletions: Vec<(Arc<SimilarImage>, Arc<SimilarImage>)>,
+    /// Bytes freed by deleting them
+    pub bytes: u64,
+    /// Files that were chosen for deletion but will be kept, either because
+    /// they are inside an archive, or because the copy kept instead of them
+    /// is itself being deleted
+    pub kept: Vec<PathBuf>,
+}
+
+impl Summary {
+    /// Add up `decisions`, made in order of importance.  A file is only
+    /// deleted while the one it was kept in favour of is not, so that
+    /// conflicting decisions never delete every copy of an image.
+    pub(crate) fn new<'a, I>(decisions: I, undecided: usize) -> Summary
+    where
+        I: IntoIterator<Item = (&'a SimilarPair, Decision)>,
+    {
+        let mut summary = Summary {
+            undecided,
+            ..Summary::default()
+        };
+        // Each file to delete, and the file kept in its place
+        let mut keepers: HashMap<&Path, &Path> = HashMap::new();
+        for (pair, decision) in decisions {
+            summary.reviewed += 1;
+            let (delete, keep): (&Arc<SimilarImage>, &Arc<SimilarImage>) = match decision {
+                Decision::KeepLeft => (&pair.right, &pair.left),
+                Decision::KeepRight => (&pair.left, &pair.right),
+                _ => continue,
+            };
+            let (delete_path, keep_path) = (delete.path.as_path(), keep.path.as_path());
+            if keepers.contains_key(delete_path) {
+                continue;
+            }
+            let keeps_another = keepers.values().any(|&k| k == delete_path);
+            if archive::is_virtual(delete_path) || keepers.contains_key(keep_path) || keeps_another
+            {
+                summary.kept.push(delete_path.to_path_buf());
+                continue;
+            }
+            keepers.insert(delete_path, keep_path);
+            summary.bytes += delete.size();
+            summary.deletions.push((delete.clone(), keep.clone()));
+        }
+        // A file passed over once may have been deleted for another pair
+        summary
+            .kept
+            .retain(|path| !keepers.contains_key(path.as_path()));
+        summary.kept.sort();
+        summary.kept.dedup();
+        summary
+    }
+
+    /// Delete the files, carrying on past any that cannot be deleted.
+    /// As the review may have been resumed long after the scan, a file is
+    /// only deleted while the file kept instead is still there and neither
+    /// has changed since.  Gives each file deleted, or why it was not.
+    pub fn apply(&self) -> Vec<Result<PathBuf, ScanError>> {
+        let mut results = Vec::with_capacity(self.deletions.len());
+        self.apply_each(|result| results.push(result));
+        results
+    }
+
+    /// The same as `apply`, but handing each result to `report` as soon as
+    /// it is known.  Checking the files reads them in full, which can take
+    /// a while.
+    pub fn apply_each<F>(&self, mut report: F)
+    where
+        F: FnMut(Result<PathBuf, ScanError>),
+    {
+        for (delete, keep) in &self.deletions {
+            report(delete_unchanged(delete, keep));
+        }
+    }
+}
+
+/// Delete one file, if neither it nor the file kept instead has changed
+fn delete_unchanged(delete: &SimilarImage, keep: &SimilarImage) -> Result<PathBuf, ScanError> {
+    for image in &[keep, delete] {
+        let digest = decode::digest(&image.path).ok();
+        if digest != Some(image.digest()) {
+            return Err(ScanError::Changed {
+                path: image.path.clone(),
+            });
+        }
+    }
+    fs::remove_file(&delete.path).map_err(|cause| ScanError::Io {
+        path: delete.path.clone(),
+        cause,
+    })?;
+    Ok(delete.path.clone())
+}
+
+/// e.g. `12 pairs reviewed, 3 not decided on.  5 files to delete, freeing 1.5 MiB.`
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} pairs reviewed, {} not decided on.  {} files to delete, freeing {}.",
+            self.reviewed,
+            self.undecided,
+            self.deletions.len(),
+            format_size(self.bytes)
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Classification;
+
+    #[test]
+    fn never_deletes_every_copy() {
+        let image = |name: &str| Arc::new(SimilarImage::test_image(PathBuf::from(name)));
+        let (a, b, c) = (image("a"), image("b"), image("c"));
+        let zipped = image("photos.zip!/d");
+        let pair = |x: &Arc<SimilarImage>, y: &Arc<SimilarImage>| {
+            SimilarPair::new(10, x.clone(), y.clone(), Classification::Edited)
+        };
+        let (ab, bc, ca, ad) = (pair(&a, &b), pair(&b, &c), pair(&c, &a), pair(&a, &zipped));
+        let decisions = vec![
+            (&ab, Decision::KeepLeft),
+            // Would delete c for b, which is already going
+            (&bc, Decision::KeepLeft),
+            // Would delete a, which b is going in favour of
+            (&ca, Decision::KeepLeft),
+            (&ad, Decision::KeepLeft),
+        ];
+        let summary = Summary::new(decisions, 3);
+
+        assert_eq!(summary.reviewed, 4);
+        assert_eq!(summary.undecided, 3);
+        assert_eq!(
+            summary.to_string(),
+            "4 pairs reviewed, 3 not decided on.  1 files to delete, freeing 0 B."
+        );
+        assert_eq!(summary.deletions, vec![(b, a)]);
+        assert_eq!(
+            summary.kept,
+            vec![
+                PathBuf::from("a"),
+                PathBuf::from("c"),
+                PathBuf::from("photos.zip!/d")
+            ]
+        );
+    }
+
+    #[test]
+    fn only_deletes_unchanged_files() {
+        let dir = std::env::temp_dir().join(format!("img_dedup_summary_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // Test images have the digest of their path, so give them that as contents
+        let image = |name: &str| {
+            let path = dir.join(name);
+            fs::write(&path, path.to_string_lossy().as_bytes()).unwrap();
+            Arc::new(SimilarImage::test_image(path))
+        };
+        let (a, b, c, d) = (image("a"), image("b"), image("c"), image("d"));
+        let pair = |x: &Arc<SimilarImage>, y: &Arc<SimilarImage>| {
+            SimilarPair::new(10, x.clone(), y.clone(), Classification::Exact)
+        };
+        let (ab, cd) = (pair(&a, &b), pair(&c, &d));
+        let summary = Summary::new(
+            vec![(&ab, Decision::KeepLeft), (&cd, Decision::KeepLeft)],
+            0,
+        );
+        // The copy of d that was to be kept was edited after the scan
+        fs::write(&c.path, b"edited").unwrap();
+
+        let results = summary.apply();
+        let (b_left, d_left) = (b.path.exists(), d.path.exists());
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(results[0].as_ref().unwrap(), &b.path);
+        match &results[1] {
+            Err(ScanError::Changed { path }) => assert_eq!(path, &c.path),
+            other => panic!("Expected c to have changed, got {:?}", other),
+        }
+        assert!(!b_left);
+        assert!(d_left);
+    }
+}
@@ -0,0 +1,102 @@
+use std::sync::{Arc, Condvar, Mutex};
+
+/// Limits how much of a shared resource, such as memory, the scanning
+/// threads may use at once.  Each user takes as many units as it needs and
+/// blocks until that many are free.  Clones share the same units.
+#[derive(Clone, Debug)]
+pub struct Throttle {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    capacity: u64,
+    available: Mutex<u64>,
+    freed: Condvar,
+}
+
+/// Units taken from a `Throttle`, handed back when dropped.
+/// They may be passed to another thread along with what they pay for.
+#[derive(Debug)]
+pub struct Permit {
+    throttle: Throttle,
+    amount: u64,
+}
+
+impl Throttle {
+    /// Create a throttle with `capacity` units to share out
+    pub fn new(capacity: u64) -> Throttle {
+        let capacity = capacity.max(1);
+        let shared = Shared {
+            capacity,
+            available: Mutex::new(capacity),
+            freed: Condvar::new(),
+        };
+        Throttle {
+            shared: Arc::new(shared),
+        }
+    }
+
+    /// Wait until `amount` units are free and take them.
+    /// Requests larger than the whole capacity wait until they can run alone.
+    pub fn acquire(&self, amount: u64) -> Permit {
+        let shared = &self.shared;
+        let amount = amount.min(shared.capacity);
+        let mut available = shared.available.lock().unwrap();
+        while *available < amount {
+            available = shared.freed.wait(available).unwrap();
+        }
+        *available -= amount;
+        Permit {
+            throttle: self.clone(),
+            amount,
+        }
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        let shared = &self.throttle.shared;
+        *shared.available.lock().unwrap() += self.amount;
+        shared.freed.notify_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn oversized_requests_run_alone() {
+        let throttle = Throttle::new(10);
+        let permit = throttle.acquire(1_000);
+        assert_eq!(*throttle.shared.available.lock().unwrap(), 0);
+        drop(permit);
+        assert_eq!(*throttle.shared.available.lock().unwrap(), 10);
+    }
+
+    #[test]
+    fn limits_concurrent_users() {
+        let throttle = Throttle::new(2);
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let workers: Vec<_> = (0..8)
+            .map(|_| {
+                let (throttle, running, peak) = (throttle.clone(), running.clone(), peak.clone());
+                thread::spawn(move || {
+                    let _permit = throttle.acquire(1);
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(5));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        workers.into_iter().for_each(|w| w.join().unwrap());
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+    }
+}
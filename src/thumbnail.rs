@@ -0,0 +1,187 @@
+//! Thumbnails in the cache shared with file managers, as described by the
+//! freedesktop.org Thumbnail Managing Standard.  They are made while hashing,
+//! from the image already decoded, so that browsing the results can show
+//! something straight away.
+
+use crate::session::replace_file;
+use crate::{archive, ScanError};
+use image::{ColorType, DynamicImage, FilterType, GenericImage};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Width and height of the "large" thumbnails
+const SIZE: u32 = 256;
+
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Where large thumbnails are kept, e.g. `~/.cache/thumbnails/large`
+fn cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("thumbnails").join("large"))
+}
+
+/// The cached thumbnail of the image at `path`, if there is one made
+/// since the image last changed
+pub fn find_thumbnail(path: &Path) -> Option<PathBuf> {
+    find_in(&cache_dir()?, path)
+}
+
+/// Save a thumbnail of `image`, decoded from `path`, unless an up to date
+/// one is there already.  Images inside archives have no thumbnails.
+pub(crate) fn save(path: &Path, image: &DynamicImage) -> Result<(), ScanError> {
+    let dir = cache_dir().ok_or_else(|| ScanError::InvalidConfig {
+        reason: "No cache directory to save thumbnails in".to_string(),
+    })?;
+    if archive::is_virtual(path) || find_in(&dir, path).is_some() {
+        return Ok(());
+    }
+    save_in(&dir, path, image)
+}
+
+fn find_in(dir: &Path, path: &Path) -> Option<PathBuf> {
+    let (uri, mtime) = (uri(path)?, mtime(path)?);
+    let thumbnail = dir.join(file_name(&uri));
+    let data = fs::read(&thumbnail).ok()?;
+    let text = text_chunks(&data);
+    let is = |key: &str, value: &str| text.iter().any(|(k, v)| k == key && v == value);
+    if is("Thumb::URI", &uri) && is("Thumb::MTime", &mtime.to_string()) {
+        Some(thumbnail)
+    } else {
+        None
+    }
+}
+
+fn save_in(dir: &Path, path: &Path, image: &DynamicImage) -> Result<(), ScanError> {
+    let io_error = |path: &Path| {
+        let path = path.to_path_buf();
+        move |cause| ScanError::Io { path, cause }
+    };
+    let (uri, mtime) = match (uri(path), mtime(path)) {
+        (Some(uri), Some(mtime)) => (uri, mtime),
+        _ => return Ok(()),
+    };
+    // Smaller images are kept at their own size
+    let (width, height) = image.dimensions();
+    let rgba = if width > SIZE || height > SIZE {
+        image.resize(SIZE, SIZE, FilterType::Triangle).to_rgba()
+    } else {
+        image.to_rgba()
+    };
+    let mut png = Vec::new();
+    image::png::PNGEncoder::new(&mut png)
+        .encode(&rgba, rgba.width(), rgba.height(), ColorType::RGBA(8))
+        .map_err(io_error(path))?;
+    let png = with_text(
+        &png,
+        &[("Thumb::URI", &uri), ("Thumb::MTime", &mtime.to_string())],
+    );
+
+    fs::create_dir_all(dir).map_err(io_error(dir))?;
+    let thumbnail = dir.join(file_name(&uri));
+    replace_file(&thumbnail, &png).map_err(io_error(&thumbnail))
+}
+
+/// The `file://` URI of an existing file, escaped the way GLib does so that
+/// thumbnails are shared with other programs
+fn uri(path: &Path) -> Option<String> {
+    let path = path.canonicalize().ok()?;
+    let mut uri = String::from("file://");
+    for &byte in path.to_string_lossy().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => uri.push(byte as char),
+            b'-' | b'_' | b'.' | b'!' | b'~' | b'*' | b'\'' | b'(' | b')' => uri.push(byte as char),
+            b'/' | b'&' | b'=' | b':' | b'@' | b'+' | b'$' | b',' => uri.push(byte as char),
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    Some(uri)
+}
+
+/// When a file was last changed, in whole seconds since the epoch
+fn mtime(path: &Path) -> Option<u64> {
+    let modified = fs::metadata(path).ok()?.modified().ok()?;
+    modified
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
+
+/// Thumbnails are named after the MD5 of the URI of their image
+fn file_name(uri: &str) -> String {
+    format!("{:x}.png", md5::compute(uri))
+}
+
+/// Add `tEXt` chunks to an encoded PNG, straight after its header
+fn with_text(png: &[u8], text: &[(&str, &str)]) -> Vec<u8> {
+    // The signature, then the header's length, type, 13 bytes of data and CRC
+    let header_end = PNG_SIGNATURE.len() + 4 + 4 + 13 + 4;
+    let mut out = png[..header_end].to_vec();
+    for (key, value) in text {
+        let mut chunk = b"tEXt".to_vec();
+        chunk.extend_from_slice(key.as_bytes());
+        chunk.push(0);
+        chunk.extend_from_slice(value.as_bytes());
+        out.extend_from_slice(&(chunk.len() as u32 - 4).to_be_bytes());
+        out.extend_from_slice(&chunk);
+        out.extend_from_slice(&crc32fast::hash(&chunk).to_be_bytes());
+    }
+    out.extend_from_slice(&png[header_end..]);
+    out
+}
+
+/// The keys and values of the `tEXt` chunks of a PNG
+fn text_chunks(png: &[u8]) -> Vec<(String, String)> {
+    let mut text = Vec::new();
+    if !png.starts_with(PNG_SIGNATURE) {
+        return text;
+    }
+    let mut rest = &png[PNG_SIGNATURE.len()..];
+    while rest.len() >= 12 {
+        let length = u32::from_be_bytes([rest[0], rest[1], rest[2], rest[3]]) as usize;
+        let (kind, data) = (&rest[4..8], &rest[8..]);
+        if data.len() < length + 4 {
+            break;
+        }
+        let data = &data[..length];
+        if kind == b"tEXt" {
+            if let Some(nul) = data.iter().position(|&b| b == 0) {
+                let (key, value) = (&data[..nul], &data[nul + 1..]);
+                text.push((
+                    String::from_utf8_lossy(key).into_owned(),
+                    String::from_utf8_lossy(value).into_owned(),
+                ));
+            }
+        } else if kind == b"IEND" {
+            break;
+        }
+        rest = &rest[8 + length + 4..];
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thumbnails_are_found_for_their_image() {
+        let dir = std::env::temp_dir().join(format!("img_dedup_thumbnails_{}", std::process::id()));
+        let original = dir.join("an image #1.png");
+        fs::create_dir_all(&dir).unwrap();
+        // Only the file's name and time are looked at
+        fs::write(&original, b"").unwrap();
+        let image = DynamicImage::new_rgb8(600, 300);
+
+        save_in(&dir, &original, &image).unwrap();
+        let found = find_in(&dir, &original);
+        let uri = uri(&original).unwrap();
+        let thumbnail = found.as_ref().map(|path| image::open(path));
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert!(uri.starts_with("file:///"));
+        assert!(uri.ends_with("/an%20image%20%231.png"));
+        let thumbnail = thumbnail.unwrap().unwrap();
+        assert_eq!(thumbnail.dimensions(), (256, 128));
+        assert!(find_in(&dir, &original).is_none());
+    }
+}
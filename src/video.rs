@@ -0,0 +1,123 @@
+use image::{DynamicImage, ImageError, ImageFormat, ImageResult};
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// Containers we hand over to ffmpeg
+const VIDEO_EXTENSIONS: [&str; 7] = ["mp4", "m4v", "mov", "webm", "mkv", "avi", "3gp"];
+
+/// Whether the (lowercase) extension belongs to a video container
+pub fn is_video(extension: &str) -> bool {
+    VIDEO_EXTENSIONS.contains(&extension)
+}
+
+/// The programs run to read videos
+#[derive(Clone, Copy)]
+struct Tools {
+    ffmpeg: &'static str,
+    ffprobe: &'static str,
+}
+
+/// Found on the `PATH`
+const TOOLS: Tools = Tools {
+    ffmpeg: "ffmpeg",
+    ffprobe: "ffprobe",
+};
+
+/// Whether `ffmpeg` and `ffprobe` can be run from the `PATH`
+pub fn ffmpeg_available() -> bool {
+    TOOLS.available()
+}
+
+/// Extract `samples` keyframes spread evenly over the length of a video
+pub fn keyframes(path: &Path, samples: usize) -> ImageResult<Vec<DynamicImage>> {
+    TOOLS.keyframes(path, samples)
+}
+
+impl Tools {
+    fn available(self) -> bool {
+        [self.ffmpeg, self.ffprobe].iter().all(|program| {
+            Command::new(program)
+                .arg("-version")
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .map(|s| s.success())
+                .unwrap_or(false)
+        })
+    }
+
+    fn keyframes(self, path: &Path, samples: usize) -> ImageResult<Vec<DynamicImage>> {
+        let duration = self.duration(path)?;
+        (0..samples)
+            .map(|i| {
+                // Aim for the middle of each segment to skip fades at either end
+                let seconds = duration * (i as f64 + 0.5) / samples as f64;
+                self.keyframe_at(path, seconds)
+            })
+            .collect()
+    }
+
+    /// Length of the video in seconds
+    fn duration(self, path: &Path) -> ImageResult<f64> {
+        let output = Command::new(self.ffprobe)
+            .args(&["-v", "error", "-show_entries", "format=duration"])
+            .args(&["-of", "default=noprint_wrappers=1:nokey=1"])
+            .arg(path)
+            .output()
+            .map_err(ImageError::IoError)?;
+        if !output.status.success() {
+            return Err(ImageError::FormatError(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+        String::from_utf8_lossy(&output.stdout)
+            .trim()
+            .parse()
+            .map_err(|_| ImageError::FormatError(format!("No duration found for {:?}", path)))
+    }
+
+    /// Decode the first keyframe at or after `seconds` into the video
+    fn keyframe_at(self, path: &Path, seconds: f64) -> ImageResult<DynamicImage> {
+        let output = Command::new(self.ffmpeg)
+            .args(&["-v", "error", "-skip_frame", "nokey"])
+            .args(&["-ss", &format!("{:.3}", seconds), "-i"])
+            .arg(path)
+            .args(&["-frames:v", "1", "-f", "image2pipe", "-vcodec", "png", "-"])
+            .stdin(Stdio::null())
+            .output()
+            .map_err(ImageError::IoError)?;
+        if !output.status.success() || output.stdout.is_empty() {
+            return Err(ImageError::FormatError(
+                String::from_utf8_lossy(&output.stderr).into_owned(),
+            ));
+        }
+        image::load_from_memory_with_format(&output.stdout, ImageFormat::PNG)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::ErrorKind;
+
+    const MISSING: Tools = Tools {
+        ffmpeg: "img_dedup_missing_ffmpeg",
+        ffprobe: "img_dedup_missing_ffprobe",
+    };
+
+    #[test]
+    fn recognises_videos_by_extension() {
+        assert!(is_video("mp4") && is_video("mkv"));
+        assert!(!is_video("gif") && !is_video("MP4"));
+    }
+
+    #[test]
+    fn missing_ffmpeg_is_an_io_error() {
+        assert!(!MISSING.available());
+        match MISSING.keyframes(Path::new("clip.mp4"), 4) {
+            Err(ImageError::IoError(e)) => assert_eq!(e.kind(), ErrorKind::NotFound),
+            Err(e) => panic!("Expected a missing program, got {}", e),
+            Ok(_) => panic!("Expected a missing program"),
+        }
+    }
+}
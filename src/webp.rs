@@ -0,0 +1,148 @@
+//! WebP files the `image` crate can't read by itself.  It only takes a
+//! single lossy picture straight after the file header, so stills with
+//! metadata or transparency, and animations, are taken apart here and their
+//! pictures handed to it one at a time.  Lossless pictures are not supported,
+//! and transparency is ignored.
+
+use crate::decode::Sampler;
+use image::{DynamicImage, GenericImage, ImageError, ImageFormat, ImageResult, Rgba, RgbaImage};
+use std::iter;
+
+/// Set in the flags of the extended header when the file is animated
+const ANIMATION_FLAG: u8 = 0x02;
+
+/// Width and height of the picture, or of the canvas of an animation,
+/// read from the headers alone
+pub(crate) fn dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let (kind, payload) = chunks(data).next()?;
+    match kind {
+        b"VP8X" if payload.len() >= 10 => Some((u24(&payload[4..7]) + 1, u24(&payload[7..10]) + 1)),
+        // A frame tag and start code come before the size
+        b"VP8 " if payload.len() >= 10 => Some((
+            u32::from(u16::from_le_bytes([payload[6], payload[7]]) & 0x3fff),
+            u32::from(u16::from_le_bytes([payload[8], payload[9]]) & 0x3fff),
+        )),
+        b"VP8L" if payload.len() >= 5 && payload[0] == 0x2f => {
+            let bits = u32::from_le_bytes([payload[1], payload[2], payload[3], payload[4]]);
+            Some(((bits & 0x3fff) + 1, (bits >> 14 & 0x3fff) + 1))
+        }
+        _ => None,
+    }
+}
+
+pub(crate) fn is_animated(data: &[u8]) -> bool {
+    match chunks(data).next() {
+        Some((b"VP8X", payload)) => payload.first().map_or(false, |f| f & ANIMATION_FLAG != 0),
+        _ => false,
+    }
+}
+
+/// The picture of a still, or the first frame of an animation
+pub(crate) fn decode(data: &[u8]) -> ImageResult<DynamicImage> {
+    frames(data, 1).map(|mut frames| frames.remove(0))
+}
+
+/// Up to `samples` frames spread evenly over an animation, each drawn over
+/// the ones before it as they would be shown.  A still gives one frame.
+pub(crate) fn frames(data: &[u8], samples: usize) -> ImageResult<Vec<DynamicImage>> {
+    if !is_animated(data) {
+        return picture(chunks(data)).map(|p| vec![DynamicImage::ImageRgba8(p)]);
+    }
+    let (width, height) = dimensions(data).ok_or(ImageError::DimensionError)?;
+    let mut canvas = RgbaImage::new(width, height);
+    let mut sampler = Sampler::new(samples);
+    for (_, frame) in chunks(data).filter(|(kind, _)| kind == b"ANMF") {
+        if frame.len() < 16 {
+            return Err(format_error("Truncated animation frame"));
+        }
+        let (x, y) = (u24(&frame[0..3]) * 2, u24(&frame[3..6]) * 2);
+        let dispose = frame[15] & 0x01 != 0;
+        let picture = picture(chunks_of(&frame[16..]))?;
+        if !canvas.copy_from(&picture, x, y) {
+            return Err(ImageError::DimensionError);
+        }
+        sampler.offer(&canvas);
+        if dispose {
+            let clear =
+                RgbaImage::from_pixel(picture.width(), picture.height(), Rgba { data: [0; 4] });
+            canvas.copy_from(&clear, x, y);
+        }
+    }
+    sampler.finish()
+}
+
+/// Decode the picture among `chunks`
+fn picture<'a, I>(mut chunks: I) -> ImageResult<RgbaImage>
+where
+    I: Iterator<Item = (&'a [u8], &'a [u8])>,
+{
+    match chunks.find(|(kind, _)| kind == b"VP8 " || kind == b"VP8L") {
+        Some((b"VP8 ", bitstream)) => {
+            // Wrap the picture in the simple file layout the image crate reads
+            let mut file = b"RIFF".to_vec();
+            file.extend_from_slice(&(12 + bitstream.len() as u32).to_le_bytes());
+            file.extend_from_slice(b"WEBPVP8 ");
+            file.extend_from_slice(&(bitstream.len() as u32).to_le_bytes());
+            file.extend_from_slice(bitstream);
+            image::load_from_memory_with_format(&file, ImageFormat::WEBP).map(|i| i.to_rgba())
+        }
+        Some(_) => Err(format_error("Lossless WebP is not supported")),
+        None => Err(format_error("WebP file has no picture")),
+    }
+}
+
+/// The chunks of a WebP file, as their four character codes and contents
+fn chunks(data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    let body = if data.len() >= 12 && &data[..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        &data[12..]
+    } else {
+        &[]
+    };
+    chunks_of(body)
+}
+
+/// Chunks one after the other, as in a file or an animation frame
+fn chunks_of(mut data: &[u8]) -> impl Iterator<Item = (&[u8], &[u8])> {
+    iter::from_fn(move || {
+        if data.len() < 8 {
+            return None;
+        }
+        let kind = &data[..4];
+        let len = u32::from_le_bytes([data[4], data[5], data[6], data[7]]) as usize;
+        let payload = data.get(8..8 + len)?;
+        // Chunks are padded to an even length
+        data = data.get(8 + len + (len & 1)..).unwrap_or(&[]);
+        Some((kind, payload))
+    })
+}
+
+/// Little endian 24 bit number, as the extended format uses
+fn u24(bytes: &[u8]) -> u32 {
+    u32::from(bytes[0]) | u32::from(bytes[1]) << 8 | u32::from(bytes[2]) << 16
+}
+
+fn format_error(reason: &str) -> ImageError {
+    ImageError::FormatError(reason.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn animations_are_drawn_frame_by_frame() {
+        // The same 16x16 picture at the left and then the right of the canvas
+        let data = fs::read("test/animated.webp").unwrap();
+        assert!(is_animated(&data));
+        assert_eq!(dimensions(&data), Some((32, 16)));
+
+        let frames = frames(&data, 8).unwrap();
+        assert_eq!(frames.len(), 2);
+        let (first, second) = (frames[0].to_rgba(), frames[1].to_rgba());
+        assert_eq!(first.dimensions(), (32, 16));
+        assert_eq!(first.get_pixel(20, 8).data[3], 0);
+        assert_eq!(second.get_pixel(20, 8).data[3], 255);
+        assert_eq!(first.get_pixel(8, 8), second.get_pixel(8, 8));
+    }
+}
@@ -1,59 +1,579 @@
 use self::Msg::*;
+use super::loader::{self, Cache, Loaded, Loader};
+use super::pairlist::Msg::{ChangeFolder, ChangeMinSimilarity, ChangeMinSize, ChangeOrder, GoTo};
+use super::pairlist::{Msg as ListMsg, PairList};
+use gdk::enums::key;
+use gdk_pixbuf::{Colorspace, InterpType, Pixbuf};
 use gtk::prelude::*;
-use gtk::Orientation::Vertical;
-use img_dedup::SimilarPair;
-use log::debug;
-use relm::{connect, Relm, Widget};
+use gtk::Orientation::{Horizontal, Vertical};
+use image::RgbImage;
+use img_dedup::{
+    find_thumbnail, Decision, Filter, IgnoreList, Metadata, Review, ScanError, SimilarImage,
+    SimilarPair, SortOrder, Summary,
+};
+use log::{debug, warn};
+use relm::{connect, connect_stream, Relm, Widget};
 use relm_attributes::widget;
 use relm_derive::Msg;
 use std::collections::BinaryHeap;
+use std::path::PathBuf;
+
+/// How much each zoom step scales by
+const ZOOM_STEP: f64 = 1.25;
+const MIN_ZOOM: f64 = 0.05;
+const MAX_ZOOM: f64 = 4.0;
+/// Largest preview we are willing to render, as huge images zoomed in
+/// would otherwise take gigabytes
+const MAX_PREVIEW_PIXELS: f64 = 64_000_000.0;
+
+/// How many pairs after the current one are decoded ahead of time
+const PREFETCH_PAIRS: usize = 3;
+
+/// How often the blink view swaps images, in milliseconds
+const BLINK_INTERVAL: u32 = 500;
+
+/// How long each frame of an animation is shown, in milliseconds.  The
+/// frames are sampled from the whole animation, so their own timing is lost.
+const FRAME_INTERVAL: u32 = 100;
+
+/// How often moving between pairs is saved to the session, in milliseconds
+const SAVE_INTERVAL: u32 = 5000;
+
+/// Shown in the cheat sheet, as keys and what they do
+const SHORTCUTS: &[(&str, &str)] = &[
+    ("L", "Keep the left image"),
+    ("R", "Keep the right image"),
+    ("B", "Keep both images"),
+    ("S", "Skip this pair"),
+    ("N", "Not duplicates, never pair them again"),
+    ("G", "None of this group are duplicates"),
+    ("U", "Undo the last choice"),
+    ("P", "Go back to the previous pair"),
+    ("+ / -", "Zoom in / out"),
+    ("0", "Zoom to fit"),
+    ("1", "Actual size"),
+    ("? or F1", "Show or hide these shortcuts"),
+];
+
+/// How big to draw the images
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Zoom {
+    /// Scale to fit the space available
+    Fit,
+    /// Scale the left image by this factor
+    Scale(f64),
+}
+
+/// How the pair is shown
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum View {
+    /// The two images next to each other
+    SideBySide,
+    /// The difference between them, brighter where they differ more
+    Heatmap,
+    /// One image after the other, in the same place
+    Blink,
+    /// The left image up to the slider, the right one after it
+    Wipe,
+}
 
 pub struct Model {
-    files: BinaryHeap<SimilarPair>,
-    current_pair: Option<SimilarPair>,
+    relm: Relm<CompareWidget>,
+    review: Review,
+    /// Where the review is saved after every decision, once it has been saved
+    session: Option<PathBuf>,
+    /// Whether the review has moved on since it was last saved
+    unsaved: bool,
+    /// Why saving the session last failed, if it did
+    session_problem: String,
+    /// Why saving the ignore list last failed, if it did
+    ignored_problem: String,
+    /// Pairs marked as not duplicates, by this review or earlier ones
+    ignored: IgnoreList,
+    /// Which pairs are listed in the sidebar, and in what order
+    filter: Filter,
+    order: SortOrder,
+    description: String,
+    left_details: String,
+    right_details: String,
+    zoom: Zoom,
+    /// Decodes the images of the pair shown and the next few
+    loader: Loader,
+    cache: Cache,
+    /// The frames of the images at full size, or their thumbnails until
+    /// they are loaded, kept for rescaling.  Stills have one frame.
+    left_frames: Vec<Pixbuf>,
+    right_frames: Vec<Pixbuf>,
+    /// The same frames scaled to the current zoom
+    left_scaled: Vec<Pixbuf>,
+    right_scaled: Vec<Pixbuf>,
+    /// How many frames have been played since the pair was shown
+    frame: usize,
+    /// Counts the pairs shown, so that the timer playing the animations of
+    /// an earlier pair stops
+    shown: u32,
+    /// Space the images were last fitted into
+    fitted_to: (i32, i32),
+    view: View,
+    /// The pair resized to the same dimensions, made by the loader when
+    /// first needed
+    aligned: Option<Diff>,
+    /// The same scaled to the size they were last drawn at
+    scaled_diff: Option<((i32, i32), Diff)>,
+    /// Whether the loader has been asked to align the pair shown
+    aligning: bool,
+    /// Whether the blink view is showing the right image
+    blink_right: bool,
+    /// Counts the times the blink view was entered, so that the timer
+    /// started for an earlier visit stops
+    blinks: u32,
+    /// Where the wipe splits the images, from 0 at the left to 1 at the right
+    wipe: f64,
+    /// The cheat sheet drawn over the images
+    help: gtk::Frame,
+}
+
+impl Model {
+    fn save_session(&mut self) -> Result<(), ScanError> {
+        if let Some(path) = &self.session {
+            self.review.save(path)?;
+        }
+        self.unsaved = false;
+        Ok(())
+    }
+}
+
+impl Drop for Model {
+    /// Keep the position reached when the window is closed between saves
+    fn drop(&mut self) {
+        if self.unsaved {
+            if let Err(e) = self.save_session() {
+                warn!("Could not save the session: {}", e);
+            }
+        }
+    }
 }
 
 #[derive(Msg)]
 pub enum Msg {
     SetFiles(BinaryHeap<SimilarPair>),
-    Next,
+    Resume(Review),
+    SetSession(PathBuf),
+    /// Sent for the window to ask where to save the session
+    SaveSessionAs,
+    /// Save the session if the review has moved on
+    SaveSession,
+    Finish,
+    /// Sent when every pair has been decided on, or the review is cut short
+    Finished(Summary),
+    /// Drop the pairs of files that were deleted
+    Forget(Vec<PathBuf>),
+    Decide(Decision),
+    /// Mark the current pair, and any images matched with both of its
+    /// images, as not duplicates
+    IgnoreGroup,
+    Undo,
+    Previous,
+    Jump(usize),
+    SetFolder(String),
+    SetMinSimilarity(usize),
+    SetMinSize(u64),
+    SetOrder(&'static str),
+    KeyPress(u32),
+    ToggleHelp,
+    HideHelp,
+    ZoomIn,
+    ZoomOut,
+    ZoomFit,
+    ZoomActual,
+    Resized,
+    /// The frames of an image decoded by the loader
+    Loaded(PathBuf, Vec<RgbImage>),
+    /// A pair brought to the same dimensions by the loader, and its heatmap
+    Aligned(PathBuf, PathBuf, RgbImage, RgbImage, RgbImage),
+    /// The details of a pair read by the loader
+    Described(PathBuf, PathBuf, Metadata, Metadata),
+    SetView(View),
+    /// Swap the images of the blink view, if it is still the visit given
+    Blink(u32),
+    /// Show the next frame of the animations, if the pair given is still shown
+    Animate(u32),
+    Wipe(f64),
 }
 
 #[widget]
 impl Widget for CompareWidget {
-    fn model(_relm: &Relm<Self>, files: BinaryHeap<SimilarPair>) -> Model {
+    fn model(relm: &Relm<Self>, files: BinaryHeap<SimilarPair>) -> Model {
+        let stream = relm.stream().clone();
         Model {
-            files,
-            current_pair: None,
+            relm: relm.clone(),
+            review: Review::new(files),
+            session: None,
+            unsaved: false,
+            session_problem: String::new(),
+            ignored_problem: String::new(),
+            ignored: super::load_ignored(),
+            filter: Filter::default(),
+            order: SortOrder::Similarity,
+            description: String::new(),
+            left_details: String::new(),
+            right_details: String::new(),
+            zoom: Zoom::Fit,
+            loader: Loader::new(move |loaded| match loaded {
+                Loaded::Image(path, frames) => stream.emit(Msg::Loaded(path, frames)),
+                Loaded::Aligned(left, right, left_image, right_image, heatmap) => {
+                    stream.emit(Aligned(left, right, left_image, right_image, heatmap))
+                }
+                Loaded::Metadata(left, right, left_metadata, right_metadata) => {
+                    stream.emit(Described(left, right, left_metadata, right_metadata))
+                }
+            }),
+            cache: Cache::default(),
+            left_frames: Vec::new(),
+            right_frames: Vec::new(),
+            left_scaled: Vec::new(),
+            right_scaled: Vec::new(),
+            frame: 0,
+            shown: 0,
+            fitted_to: (0, 0),
+            view: View::SideBySide,
+            aligned: None,
+            scaled_diff: None,
+            aligning: false,
+            blink_right: false,
+            blinks: 0,
+            wipe: 0.5,
+            help: cheat_sheet(),
         }
     }
 
+    fn subscriptions(&mut self, relm: &Relm<Self>) {
+        relm::interval(relm.stream(), SAVE_INTERVAL, || SaveSession);
+    }
+
+    fn init_view(&mut self) {
+        self.overlay.add_overlay(&self.model.help);
+        // Both previews share scroll positions, so panning one pans the other
+        self.rightscroll
+            .set_hadjustment(self.leftscroll.get_hadjustment().as_ref());
+        self.rightscroll
+            .set_vadjustment(self.leftscroll.get_vadjustment().as_ref());
+    }
+
     fn update(&mut self, event: Msg) {
         match event {
             SetFiles(files) => {
-                self.model.files = files;
-                self.next();
+                self.model.review = Review::new(files);
+                self.model.session = None;
+                self.refresh_list();
+                self.show_current();
+            }
+            Resume(review) => {
+                self.model.review = review;
+                self.refresh_list();
+                self.show_current();
+            }
+            SetSession(path) => {
+                self.model.session = Some(path);
+                self.save_session();
+            }
+            SaveSessionAs | Finished(_) => (),
+            SaveSession => {
+                if self.model.unsaved {
+                    self.save_session();
+                }
+            }
+            Finish => self.finish(),
+            Forget(paths) => {
+                self.model.review.forget(&paths);
+                self.refresh_list();
+                self.show_current();
+                self.save_session();
+            }
+            Decide(decision) => {
+                let position = self.model.review.position();
+                self.decide(&[position], decision);
+            }
+            IgnoreGroup => {
+                let group = self.model.review.group(self.model.review.position());
+                self.decide(&group, Decision::Ignore);
+            }
+            Undo => {
+                if let Some((_, indices)) = self.model.review.undo() {
+                    self.decided(&indices);
+                }
+            }
+            Previous => {
+                self.model.review.previous();
+                self.show_current();
+            }
+            Jump(index) => {
+                self.model.review.go_to(index);
+                self.show_current();
+            }
+            SetFolder(folder) => {
+                self.model.filter.folder = folder;
+                self.refresh_list();
+            }
+            SetMinSimilarity(similarity) => {
+                self.model.filter.min_similarity = similarity;
+                self.refresh_list();
+            }
+            SetMinSize(size) => {
+                self.model.filter.min_size = size;
+                self.refresh_list();
+            }
+            SetOrder(name) => {
+                if let Ok(order) = name.parse() {
+                    self.model.order = order;
+                    self.refresh_list();
+                }
+            }
+            KeyPress(keyval) => {
+                if let Some(msg) = shortcut(keyval) {
+                    self.update(msg);
+                }
+            }
+            ToggleHelp => {
+                let visible = self.model.help.get_visible();
+                self.model.help.set_visible(!visible);
+            }
+            HideHelp => self.model.help.hide(),
+            ZoomIn => self.set_zoom(self.scale() * ZOOM_STEP),
+            ZoomOut => self.set_zoom(self.scale() / ZOOM_STEP),
+            ZoomActual => self.set_zoom(1.0),
+            ZoomFit => {
+                self.model.zoom = Zoom::Fit;
+                self.render();
+            }
+            Resized => {
+                if self.model.zoom == Zoom::Fit && self.model.fitted_to != self.available_size() {
+                    self.render();
+                }
+            }
+            Msg::Loaded(path, frames) => self.loaded(path, frames),
+            Aligned(left, right, left_image, right_image, heatmap) => {
+                let current = self.model.review.current();
+                if current.map_or(false, |p| p.left.path == left && p.right.path == right) {
+                    self.model.aligned = Some(Diff {
+                        left: to_pixbuf(left_image),
+                        right: to_pixbuf(right_image),
+                        heatmap: to_pixbuf(heatmap),
+                    });
+                    self.model.scaled_diff = None;
+                    self.render();
+                }
+            }
+            Described(left, right, left_metadata, right_metadata) => {
+                if let Some(pair) = self.model.review.current() {
+                    if pair.left.path == left && pair.right.path == right {
+                        self.model.left_details =
+                            details_markup(&pair.left, &left_metadata, &right_metadata);
+                        self.model.right_details =
+                            details_markup(&pair.right, &right_metadata, &left_metadata);
+                    }
+                }
+            }
+            SetView(view) => {
+                if view == View::Blink && self.model.view != View::Blink {
+                    self.model.blinks = self.model.blinks.wrapping_add(1);
+                    self.blink_later();
+                }
+                self.model.view = view;
+                self.render();
+            }
+            Blink(visit) => {
+                if self.model.view == View::Blink && visit == self.model.blinks {
+                    self.model.blink_right = !self.model.blink_right;
+                    self.render();
+                    self.blink_later();
+                }
+            }
+            Animate(shown) => {
+                if shown == self.model.shown {
+                    self.model.frame += 1;
+                    self.show_frame();
+                    self.animate_later();
+                }
+            }
+            Wipe(position) => {
+                self.model.wipe = position;
+                self.render();
             }
-            Next => self.next(),
         };
     }
 
     view! {
-        gtk::Box {
-            orientation: Vertical,
-            gtk::Label {
-                text: "Image Deduplicator",
+        gtk::Paned {
+            orientation: Horizontal,
+            #[name="pairs"]
+            PairList() {
+                GoTo(index) => Jump(index),
+                ChangeFolder(ref folder) => SetFolder(folder.clone()),
+                ChangeMinSimilarity(similarity) => SetMinSimilarity(similarity),
+                ChangeMinSize(size) => SetMinSize(size),
+                ChangeOrder(order) => SetOrder(order),
             },
-                #[name="leftimage"]
-                gtk::Image {
+            gtk::Box {
+                orientation: Vertical,
+                gtk::Label {
+                    text: "Image Deduplicator",
+                },
+                gtk::Label {
+                    text: &self.model.description,
+                },
+                gtk::Label {
+                    text: &self.model.session_problem,
+                    visible: !self.model.session_problem.is_empty(),
+                },
+                gtk::Label {
+                    text: &self.model.ignored_problem,
+                    visible: !self.model.ignored_problem.is_empty(),
+                },
+                #[name="overlay"]
+                gtk::Overlay {
+                    gtk::Box {
+                        orientation: Vertical,
+                        gtk::Box {
+                            orientation: Horizontal,
+                            homogeneous: true,
+                            visible: self.model.view == View::SideBySide,
+                            gtk::Box {
+                                orientation: Vertical,
+                                #[name="leftscroll"]
+                                gtk::ScrolledWindow {
+                                    vexpand: true,
+                                    min_content_height: 300,
+                                    size_allocate(_, _) => Resized,
+                                    #[name="leftimage"]
+                                    gtk::Image {
+                                    },
+                                },
+                                gtk::Label {
+                                    markup: &self.model.left_details,
+                                    selectable: true,
+                                },
+                            },
+                            gtk::Box {
+                                orientation: Vertical,
+                                #[name="rightscroll"]
+                                gtk::ScrolledWindow {
+                                    vexpand: true,
+                                    min_content_height: 300,
+                                    #[name="rightimage"]
+                                    gtk::Image {
+                                    },
+                                },
+                                gtk::Label {
+                                    markup: &self.model.right_details,
+                                    selectable: true,
+                                },
+                            },
+                        },
+                        #[name="diffscroll"]
+                        gtk::ScrolledWindow {
+                            vexpand: true,
+                            min_content_height: 300,
+                            visible: self.model.view != View::SideBySide,
+                            size_allocate(_, _) => Resized,
+                            #[name="diffimage"]
+                            gtk::Image {
+                            },
+                        },
+                    },
                 },
-                #[name="rightimage"]
-                gtk::Image {
+                gtk::Scale {
+                    orientation: Horizontal,
+                    adjustment: &gtk::Adjustment::new(0.5, 0.0, 1.0, 0.01, 0.1, 0.0),
+                    draw_value: false,
+                    visible: self.model.view == View::Wipe,
+                    value_changed(scale) => Wipe(scale.get_value()),
+                },
+                gtk::Box {
+                    orientation: Horizontal,
+                    gtk::Button {
+                        clicked => SetView(View::SideBySide),
+                        label: "Side by Side",
+                    },
+                    gtk::Button {
+                        clicked => SetView(View::Heatmap),
+                        label: "Difference",
+                    },
+                    gtk::Button {
+                        clicked => SetView(View::Blink),
+                        label: "Blink",
+                    },
+                    gtk::Button {
+                        clicked => SetView(View::Wipe),
+                        label: "Wipe",
+                    },
+                },
+                gtk::Box {
+                    orientation: Horizontal,
+                    gtk::Button {
+                        clicked => ZoomFit,
+                        label: "Fit",
+                    },
+                    gtk::Button {
+                        clicked => ZoomActual,
+                        label: "100%",
+                    },
+                    gtk::Button {
+                        clicked => ZoomOut,
+                        label: "Zoom Out",
+                    },
+                    gtk::Button {
+                        clicked => ZoomIn,
+                        label: "Zoom In",
+                    },
+                },
+                gtk::Box {
+                    orientation: Horizontal,
+                    gtk::Button {
+                        clicked => Decide(Decision::KeepLeft),
+                        label: "Keep Left",
+                    },
+                    gtk::Button {
+                        clicked => Decide(Decision::KeepBoth),
+                        label: "Keep Both",
+                    },
+                    gtk::Button {
+                        clicked => Decide(Decision::KeepRight),
+                        label: "Keep Right",
+                    },
+                    gtk::Button {
+                        clicked => Decide(Decision::Skip),
+                        label: "Skip",
+                    },
+                    gtk::Button {
+                        clicked => Decide(Decision::Ignore),
+                        label: "Not Duplicates",
+                    },
+                    gtk::Button {
+                        clicked => IgnoreGroup,
+                        label: "Group Not Duplicates",
+                    },
+                    gtk::Button {
+                        clicked => Previous,
+                        label: "Previous",
+                    },
+                    gtk::Button {
+                        clicked => Undo,
+                        label: "Undo",
+                    },
+                    gtk::Button {
+                        clicked => SaveSessionAs,
+                        label: "Save Session",
+                    },
+                    gtk::Button {
+                        clicked => Finish,
+                        label: "Finish Review",
+                    },
+                    gtk::Button {
+                        clicked => ToggleHelp,
+                        label: "Shortcuts",
+                    },
                 },
-            #[name="nextbutton"]
-            gtk::Button {
-                clicked => Next,
-                label: "Next Pair",
             },
         },
     }
@@ -61,12 +581,468 @@ impl Widget for CompareWidget {
 
 // Maybe more of this should be moved to the library
 impl CompareWidget {
-    fn next(&mut self) {
-        self.model.current_pair = self.model.files.pop();
-        if let Some(pair) = &self.model.current_pair {
+    fn show_current(&mut self) {
+        self.model.aligned = None;
+        self.model.scaled_diff = None;
+        self.model.aligning = false;
+        self.model.frame = 0;
+        self.model.shown = self.model.shown.wrapping_add(1);
+        let review = &self.model.review;
+        let (position, remaining) = (review.position(), review.remaining());
+        self.pairs.emit(ListMsg::Select(position));
+        if let Some((pair, decision)) = review.get(position) {
             debug!("{:?}", pair);
-            self.leftimage.set_from_file(&pair.left.borrow().path);
-            self.rightimage.set_from_file(&pair.right.borrow().path);
+            let decision = decision.map_or(String::new(), |d| format!(", {}", d));
+            self.model.description = format!(
+                "Pair {} of {}: {} (similarity {}){}, {} undecided",
+                position + 1,
+                review.len(),
+                pair.classification,
+                pair.similarity,
+                decision,
+                remaining
+            );
+            let cache = &mut self.model.cache;
+            self.model.left_frames = load_image(&self.leftimage, &pair.left, cache);
+            self.model.right_frames = load_image(&self.rightimage, &pair.right, cache);
+            if pair.left.is_animated() || pair.right.is_animated() {
+                self.animate_later();
+            }
+            // Left empty until the loader has read the details
+            self.model.left_details = String::new();
+            self.model.right_details = String::new();
+            let (left, right) = (pair.left.clone(), pair.right.clone());
+            self.model.loader.describe(left, right);
         }
+        self.prefetch();
+        self.render();
+        // The position is saved too, so a resumed session starts on this pair
+        self.model.unsaved = true;
     }
+
+    /// Have the loader decode the images of the current pair and then
+    /// those of the next few, leaving out any already cached.  The cache
+    /// keeps these over the rest.
+    fn prefetch(&mut self) {
+        let review = &self.model.review;
+        let indices = Some(review.position())
+            .into_iter()
+            .chain(review.upcoming().take(PREFETCH_PAIRS));
+        let mut paths: Vec<PathBuf> = Vec::new();
+        for (pair, _) in indices.filter_map(|i| review.get(i)) {
+            for image in &[&pair.left, &pair.right] {
+                if !paths.contains(&image.path) {
+                    paths.push(image.path.clone());
+                }
+            }
+        }
+        let cache = &mut self.model.cache;
+        let missing = paths
+            .iter()
+            .filter(|p| !cache.contains(p))
+            .cloned()
+            .collect();
+        self.model.loader.load(missing);
+        cache.want(paths);
+    }
+
+    /// Cache an image decoded by the loader, and swap it in if it is being shown
+    fn loaded(&mut self, path: PathBuf, frames: Vec<RgbImage>) {
+        let frames: Vec<Pixbuf> = frames.into_iter().map(to_pixbuf).collect();
+        let bytes = loader::size(&frames);
+        self.model.cache.insert(path.clone(), frames.clone(), bytes);
+        let pair = match self.model.review.get(self.model.review.position()) {
+            Some((pair, _)) => pair,
+            None => return,
+        };
+        if pair.left.path == path {
+            self.model.left_frames = frames;
+        } else if pair.right.path == path {
+            self.model.right_frames = frames;
+        } else {
+            return;
+        }
+        self.render();
+    }
+
+    fn finish(&self) {
+        let summary = self.model.review.summary();
+        self.model.relm.stream().emit(Finished(summary));
+    }
+
+    fn decide(&mut self, indices: &[usize], decision: Decision) {
+        self.model.review.decide_all(indices, decision);
+        self.decided(indices);
+    }
+
+    /// Show changes to the decisions about some pairs, and keep the ignore
+    /// list in step with them
+    fn decided(&mut self, indices: &[usize]) {
+        let mut changed = false;
+        for &index in indices {
+            if let Some((pair, decision)) = self.model.review.get(index) {
+                let ignore = decision == Some(Decision::Ignore);
+                if ignore != self.model.ignored.is_ignored(pair) {
+                    if ignore {
+                        self.model.ignored.ignore(pair);
+                    } else {
+                        self.model.ignored.unignore(pair);
+                    }
+                    changed = true;
+                }
+            }
+            self.update_row(index);
+        }
+        if changed {
+            self.model.ignored_problem = match self.model.ignored.save() {
+                Ok(_) => String::new(),
+                Err(e) => {
+                    warn!("Could not save the pairs that are not duplicates: {}", e);
+                    format!("Could not save the pairs that are not duplicates: {}", e)
+                }
+            };
+        }
+        self.show_current();
+        self.save_session();
+        if self.model.review.current().is_none() {
+            self.finish();
+        }
+    }
+
+    fn save_session(&mut self) {
+        self.model.session_problem = match self.model.save_session() {
+            Ok(()) => String::new(),
+            Err(e) => format!("Could not save the session: {}", e),
+        };
+    }
+
+    /// List the pairs let through by the filter in the sidebar
+    fn refresh_list(&self) {
+        let review = &self.model.review;
+        let rows = review
+            .list(&self.model.filter, self.model.order)
+            .into_iter()
+            .map(|index| (index, row_text(review, index)))
+            .collect();
+        self.pairs.emit(ListMsg::SetRows(rows));
+        self.pairs.emit(ListMsg::Select(review.position()));
+    }
+
+    /// Show a change in the decision about a pair in the sidebar
+    fn update_row(&self, index: usize) {
+        let text = row_text(&self.model.review, index);
+        self.pairs.emit(ListMsg::UpdateRow(index, text));
+    }
+
+    /// The image sizes are based on the first frame of the left one, or
+    /// of the right if the left has neither loaded nor got a thumbnail
+    fn reference(&self) -> Option<&Pixbuf> {
+        self.model
+            .left_frames
+            .first()
+            .or_else(|| self.model.right_frames.first())
+    }
+
+    fn available_size(&self) -> (i32, i32) {
+        let scroll = if self.model.view == View::SideBySide {
+            &self.leftscroll
+        } else {
+            &self.diffscroll
+        };
+        (scroll.get_allocated_width(), scroll.get_allocated_height())
+    }
+
+    /// How much the reference image is currently scaled by
+    fn scale(&self) -> f64 {
+        match (self.model.zoom, self.reference()) {
+            (Zoom::Scale(scale), _) => scale,
+            (Zoom::Fit, Some(pixbuf)) => {
+                let (width, height) = self.available_size();
+                let scale_x = f64::from(width) / f64::from(pixbuf.get_width());
+                let scale_y = f64::from(height) / f64::from(pixbuf.get_height());
+                scale_x.min(scale_y).max(MIN_ZOOM)
+            }
+            (Zoom::Fit, None) => 1.0,
+        }
+    }
+
+    fn set_zoom(&mut self, scale: f64) {
+        let mut scale = scale.max(MIN_ZOOM).min(MAX_ZOOM);
+        if let Some(pixbuf) = self.reference() {
+            let pixels = f64::from(pixbuf.get_width()) * f64::from(pixbuf.get_height());
+            scale = scale.min((MAX_PREVIEW_PIXELS / pixels).sqrt());
+        }
+        self.model.zoom = Zoom::Scale(scale);
+        self.render();
+    }
+
+    /// Draw both images at the current zoom.
+    /// The right image is drawn at the size of the left one, so that the
+    /// same region of each lines up while they pan together.
+    fn render(&mut self) {
+        let scale = self.scale();
+        let (width, height) = match self.reference() {
+            Some(pixbuf) => (
+                f64::from(pixbuf.get_width()) * scale,
+                f64::from(pixbuf.get_height()) * scale,
+            ),
+            None => return,
+        };
+        if self.model.view != View::SideBySide {
+            self.render_diff(width, height);
+            self.model.fitted_to = self.available_size();
+            return;
+        }
+        self.model.left_scaled = fit_frames(&self.model.left_frames, width, height);
+        self.model.right_scaled = fit_frames(&self.model.right_frames, width, height);
+        self.show_frame();
+        self.model.fitted_to = self.available_size();
+    }
+
+    /// Show the current frame of both images, as `render` last scaled them
+    fn show_frame(&self) {
+        if self.model.view != View::SideBySide {
+            return;
+        }
+        let images = [
+            (&self.leftimage, &self.model.left_scaled),
+            (&self.rightimage, &self.model.right_scaled),
+        ];
+        for (widget, frames) in images.iter() {
+            if !frames.is_empty() {
+                widget.set_from_pixbuf(&frames[self.model.frame % frames.len()]);
+            }
+        }
+    }
+
+    /// Show the next frame of the animations after a while.  The timer
+    /// only runs while the pair is shown.
+    fn animate_later(&self) {
+        let shown = self.model.shown;
+        relm::timeout(self.model.relm.stream(), FRAME_INTERVAL, move || {
+            Animate(shown)
+        });
+    }
+
+    /// Swap the images of the blink view after a while.  The timer only
+    /// runs while the view is shown.
+    fn blink_later(&self) {
+        let visit = self.model.blinks;
+        relm::timeout(self.model.relm.stream(), BLINK_INTERVAL, move || {
+            Blink(visit)
+        });
+    }
+
+    /// Draw the diff view at `width` by `height`, once the loader has
+    /// aligned the pair.  Until then the view is left empty.  The images
+    /// are only scaled again when the size changes.
+    fn render_diff(&mut self, width: f64, height: f64) {
+        let aligned = match &self.model.aligned {
+            Some(aligned) => aligned,
+            None => {
+                if let (Some(pair), false) = (self.model.review.current(), self.model.aligning) {
+                    let (left, right) = (pair.left.path.clone(), pair.right.path.clone());
+                    self.model.loader.align(left, right);
+                    self.model.aligning = true;
+                }
+                self.diffimage.clear();
+                return;
+            }
+        };
+        let size = (width.max(1.0) as i32, height.max(1.0) as i32);
+        if self.model.scaled_diff.as_ref().map(|(s, _)| *s) != Some(size) {
+            self.model.scaled_diff = aligned.scale(size).map(|diff| (size, diff));
+        }
+        let diff = match &self.model.scaled_diff {
+            Some((_, diff)) => diff,
+            None => return,
+        };
+        let pixbuf = match self.model.view {
+            View::Heatmap => Some(diff.heatmap.clone()),
+            View::Blink if self.model.blink_right => Some(diff.right.clone()),
+            View::Wipe => wipe(&diff.left, &diff.right, self.model.wipe),
+            _ => Some(diff.left.clone()),
+        };
+        if let Some(pixbuf) = pixbuf {
+            self.diffimage.set_from_pixbuf(&pixbuf);
+        }
+    }
+}
+
+/// The pixbufs the diff views are drawn from
+struct Diff {
+    left: Pixbuf,
+    right: Pixbuf,
+    /// Brighter where the images differ more
+    heatmap: Pixbuf,
+}
+
+impl Diff {
+    fn scale(&self, (width, height): (i32, i32)) -> Option<Diff> {
+        let scale = |pixbuf: &Pixbuf| pixbuf.scale_simple(width, height, InterpType::Bilinear);
+        Some(Diff {
+            left: scale(&self.left)?,
+            right: scale(&self.right)?,
+            heatmap: scale(&self.heatmap)?,
+        })
+    }
+}
+
+/// The `left` pixbuf up to `position` (from 0 to 1) of the way across, and
+/// the `right` one after it, split by a thin white line.  Both must be the
+/// same size.
+fn wipe(left: &Pixbuf, right: &Pixbuf, position: f64) -> Option<Pixbuf> {
+    let image = left.copy()?;
+    let (width, height) = (image.get_width(), image.get_height());
+    let split = ((f64::from(width) * position.max(0.0).min(1.0)) as i32).min(width - 1);
+    right.copy_area(split, 0, width - split, height, &image, split, 0);
+    for y in 0..height {
+        image.put_pixel(split, y, 255, 255, 255, 255);
+    }
+    Some(image)
+}
+
+/// How a pair is described in the sidebar
+fn row_text(review: &Review, index: usize) -> String {
+    let (pair, decision) = match review.get(index) {
+        Some(entry) => entry,
+        None => return String::new(),
+    };
+    let name = |image: &SimilarImage| {
+        image
+            .path
+            .file_name()
+            .map_or(String::new(), |n| n.to_string_lossy().into_owned())
+    };
+    format!(
+        "{} {}\n{}\n{}",
+        pair.similarity,
+        decision.map_or("Undecided".to_string(), |d| d.to_string()),
+        name(&pair.left),
+        name(&pair.right)
+    )
+}
+
+/// The message for a key pressed while comparing, if it is a shortcut
+fn shortcut(keyval: u32) -> Option<Msg> {
+    let msg = match keyval {
+        key::l | key::L => Decide(Decision::KeepLeft),
+        key::r | key::R => Decide(Decision::KeepRight),
+        key::b | key::B => Decide(Decision::KeepBoth),
+        key::s | key::S => Decide(Decision::Skip),
+        key::n | key::N => Decide(Decision::Ignore),
+        key::g | key::G => IgnoreGroup,
+        key::u | key::U => Undo,
+        key::p | key::P => Previous,
+        key::plus | key::equal | key::KP_Add => ZoomIn,
+        key::minus | key::KP_Subtract => ZoomOut,
+        key::_0 | key::KP_0 => ZoomFit,
+        key::_1 | key::KP_1 => ZoomActual,
+        key::question | key::F1 => ToggleHelp,
+        key::Escape => HideHelp,
+        _ => return None,
+    };
+    Some(msg)
+}
+
+/// A frame listing the `SHORTCUTS`, hidden until asked for
+fn cheat_sheet() -> gtk::Frame {
+    let text = SHORTCUTS
+        .iter()
+        .map(|(keys, action)| format!("<b>{}</b>\t{}", keys, action))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let label = gtk::Label::new(None);
+    label.set_markup(&text);
+    label.set_margin_start(12);
+    label.set_margin_end(12);
+    label.set_margin_top(12);
+    label.set_margin_bottom(12);
+    label.show();
+
+    let frame = gtk::Frame::new(Some("Keyboard Shortcuts"));
+    frame.add(&label);
+    frame.set_halign(gtk::Align::Center);
+    frame.set_valign(gtk::Align::Center);
+    // Gives the frame a solid background in most themes
+    if let Some(context) = frame.get_style_context() {
+        context.add_class("app-notification");
+    }
+    // Otherwise the window showing all its children would show it too
+    frame.set_no_show_all(true);
+    frame
+}
+
+/// Pango markup listing the details of `image`,
+/// with those that differ from the `other` image highlighted
+fn details_markup(image: &SimilarImage, metadata: &Metadata, other: &Metadata) -> String {
+    let mut lines = vec![format!(
+        "<b>{}</b>",
+        escape(&image.path.display().to_string())
+    )];
+    for ((label, value), (_, other_value)) in metadata.describe().into_iter().zip(other.describe())
+    {
+        if value == other_value {
+            lines.push(format!("{}: {}", label, escape(&value)));
+        } else {
+            let highlighted = format!("<span background=\"#fce94f\">{}</span>", escape(&value));
+            lines.push(format!("{}: {}", label, highlighted));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Escape text for use in Pango markup
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Give the frames of an image for `render` to scale: at full size if it
+/// has been decoded already, or else its thumbnail until the loader decodes
+/// it.  With neither there are no frames to show yet.
+fn load_image(widget: &gtk::Image, image: &SimilarImage, cache: &mut Cache) -> Vec<Pixbuf> {
+    widget.clear();
+    if let Some(frames) = cache.get(&image.path) {
+        return frames;
+    }
+    let thumbnail = match find_thumbnail(&image.path) {
+        Some(thumbnail) => thumbnail,
+        None => return Vec::new(),
+    };
+    Pixbuf::new_from_file(&thumbnail)
+        .map_err(|e| debug!("Could not load thumbnail {:?}: {}", thumbnail, e))
+        .into_iter()
+        .collect()
+}
+
+/// Scale the frames of an image to fit within `width` by `height`,
+/// keeping its aspect ratio
+fn fit_frames(frames: &[Pixbuf], width: f64, height: f64) -> Vec<Pixbuf> {
+    frames
+        .iter()
+        .filter_map(|pixbuf| {
+            let fit = (width / f64::from(pixbuf.get_width()))
+                .min(height / f64::from(pixbuf.get_height()));
+            pixbuf.scale_simple(
+                (f64::from(pixbuf.get_width()) * fit).max(1.0) as i32,
+                (f64::from(pixbuf.get_height()) * fit).max(1.0) as i32,
+                InterpType::Bilinear,
+            )
+        })
+        .collect()
+}
+
+fn to_pixbuf(rgb: RgbImage) -> Pixbuf {
+    let (width, height) = rgb.dimensions();
+    Pixbuf::new_from_vec(
+        rgb.into_raw(),
+        Colorspace::Rgb,
+        false,
+        8,
+        width as i32,
+        height as i32,
+        width as i32 * 3,
+    )
 }
@@ -1,7 +1,7 @@
-use super::radiowidget::{Msg::Clicked, RadioWidget};
+use super::radiowidget::{Msg::Clicked, Msg::Select, RadioWidget};
 use gtk::prelude::*;
 use gtk::Orientation::Vertical;
-use img_dedup::HashType;
+use img_dedup::{Config, HashType, Preset};
 use relm::{connect, connect_stream};
 use relm::{Relm, Widget};
 use relm_attributes::widget;
@@ -12,30 +12,94 @@ use self::Msg::*;
 
 pub struct Model {
     directory: PathBuf,
+    /// The preset to show as chosen
+    preset: Option<&'static str>,
+    /// The hashing method to show as chosen at first, when no preset uses it
+    method: Option<&'static str>,
     hash_size: u32,
+    video: bool,
+    archives: bool,
+    threads: usize,
+    io_threads: usize,
+    ordered_reads: bool,
+    profile: String,
 }
 
 #[derive(Msg)]
 pub enum Msg {
     OpenFileChooser,
-    ChangeDirectory(PathBuf),
+    /// Show these settings, as loaded for another folder
+    SetConfig(Config),
+    ChangePreset(&'static str),
     ChangeMethod(&'static str),
     ChangeHashLen(u32),
+    ToggleVideo(bool),
+    ToggleArchives(bool),
+    ChangeThreads(usize),
+    ChangeIoThreads(usize),
+    ToggleOrderedReads(bool),
+    ChangeProfile(String),
+    SaveProfile,
+    OpenSession,
     Deduplicate,
 }
 
 #[widget]
 impl Widget for ConfigWidget {
-    fn model(_relm: &Relm<Self>, (directory, hash_size): (PathBuf, u32)) -> Model {
+    fn model(_relm: &Relm<Self>, config: Config) -> Model {
         Model {
-            directory,
-            hash_size,
+            preset: preset_name(&config),
+            method: method_name(&config.method),
+            directory: config.directory,
+            hash_size: config.hash_size,
+            video: config.video,
+            archives: config.archives,
+            threads: config.threads,
+            io_threads: config.io_threads,
+            ordered_reads: config.ordered_reads,
+            profile: config.profile.unwrap_or_default(),
+        }
+    }
+
+    fn init_view(&mut self) {
+        // The first preset and method are active until told otherwise.
+        // Choosing a preset chooses its method too.
+        if let Some(preset) = self.model.preset {
+            self.presets.emit(Select(preset));
+        } else if let Some(method) = self.model.method {
+            self.methods.emit(Select(method));
         }
     }
 
     fn update(&mut self, event: Msg) {
-        if let ChangeDirectory(directory) = event {
-            self.model.directory = directory;
+        match event {
+            SetConfig(config) => {
+                self.model.preset = preset_name(&config);
+                if let Some(preset) = self.model.preset {
+                    self.presets.emit(Select(preset));
+                }
+                self.model.directory = config.directory;
+                self.model.video = config.video;
+                self.model.archives = config.archives;
+                self.model.ordered_reads = config.ordered_reads;
+                self.model.profile = config.profile.unwrap_or_default();
+                self.hashlength.set_value(f64::from(config.hash_size));
+                self.threads.set_value(config.threads as f64);
+                self.iothreads.set_value(config.io_threads as f64);
+                if let Some(method) = method_name(&config.method) {
+                    self.methods.emit(Select(method));
+                }
+            }
+            // Move the other controls, which pass the changes on
+            ChangePreset(name) => {
+                if let Ok(preset) = name.parse::<Preset>() {
+                    self.hashlength.set_value(f64::from(preset.hash_size()));
+                    if let Some(method) = method_name(&preset.method()) {
+                        self.methods.emit(Select(method));
+                    }
+                }
+            }
+            _ => (),
         }
     }
 
@@ -49,6 +113,14 @@ impl Widget for ConfigWidget {
             gtk::Label {
                 text: &self.model.directory.to_str().unwrap(),
             },
+            gtk::Label {
+                text: "Preset",
+            },
+            #[name="presets"]
+            RadioWidget(Preset::available_presets()) {
+                Clicked(p) => ChangePreset(p)
+            },
+            #[name="methods"]
             RadioWidget(HashType::available_methods()) {
                 // I don't really like passing around this as a string.
                 Clicked(m) => ChangeMethod(m)
@@ -58,13 +130,77 @@ impl Widget for ConfigWidget {
             },
             #[name="hashlength"]
             gtk::SpinButton {
-                adjustment: &gtk::Adjustment::new(self.model.hash_size.into(), 0., f64::from(::std::u32::MAX), 1., 8., 1.,),
+                adjustment: &gtk::Adjustment::new(self.model.hash_size.into(), Config::MIN_HASH_SIZE.into(), Config::MAX_HASH_SIZE.into(), 1., 8., 0.,),
                 value_changed(w) => ChangeHashLen(w.get_value_as_int() as u32),
             },
+            gtk::CheckButton {
+                label: "Include videos (requires ffmpeg)",
+                active: self.model.video,
+                toggled(w) => ToggleVideo(w.get_active()),
+            },
+            gtk::CheckButton {
+                label: "Look inside zip and tar archives",
+                active: self.model.archives,
+                toggled(w) => ToggleArchives(w.get_active()),
+            },
+            gtk::Label {
+                text: "Hashing threads (0 for one per CPU)",
+            },
+            #[name="threads"]
+            gtk::SpinButton {
+                adjustment: &gtk::Adjustment::new(self.model.threads as f64, 0., 256., 1., 4., 0.,),
+                value_changed(w) => ChangeThreads(w.get_value_as_int() as usize),
+            },
+            gtk::Label {
+                text: "Files read at once (0 for one per hashing thread)",
+            },
+            #[name="iothreads"]
+            gtk::SpinButton {
+                adjustment: &gtk::Adjustment::new(self.model.io_threads as f64, 0., 256., 1., 4., 0.,),
+                value_changed(w) => ChangeIoThreads(w.get_value_as_int() as usize),
+            },
+            gtk::CheckButton {
+                label: "Read files in disk order (for hard drives)",
+                active: self.model.ordered_reads,
+                toggled(w) => ToggleOrderedReads(w.get_active()),
+            },
+            gtk::Label {
+                text: "Profile",
+            },
+            gtk::Entry {
+                text: &self.model.profile,
+                changed(e) => ChangeProfile(e.get_text().unwrap_or_default()),
+            },
+            gtk::Button {
+                clicked => SaveProfile,
+                label: "Save Profile",
+            },
             gtk::Button {
                 clicked => Deduplicate,
                 label: "Deduplicate!",
             },
+            gtk::Button {
+                clicked => OpenSession,
+                label: "Resume Saved Session",
+            },
         },
     }
 }
+
+/// The name of the preset `config` hashes with, as the preset buttons know it
+fn preset_name(config: &Config) -> Option<&'static str> {
+    let preset = Preset::of(config)?.to_string();
+    Preset::available_presets()
+        .into_iter()
+        .map(|(name, _)| name)
+        .find(|&name| name == preset)
+}
+
+/// The name of `method` as the method buttons know it
+fn method_name(method: &HashType) -> Option<&'static str> {
+    let method = method.to_string();
+    HashType::available_methods()
+        .into_iter()
+        .map(|(name, _)| name)
+        .find(|&name| name == method)
+}
@@ -0,0 +1,326 @@
+use gdk_pixbuf::Pixbuf;
+use image::{DynamicImage, RgbImage};
+use img_dedup::{self as scanner, Metadata, SimilarImage};
+use log::warn;
+use relm::Channel;
+use std::collections::VecDeque;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Sender, TryRecvError};
+use std::sync::Arc;
+use std::thread;
+
+/// Most bytes of decoded images the cache holds on to
+const CACHE_BYTES: usize = 512 * 1024 * 1024;
+
+/// Most frames of an animation that are decoded to play it
+const ANIMATION_FRAMES: usize = 24;
+
+/// Work asked of the loader
+enum Request {
+    Load(Vec<PathBuf>),
+    Align(PathBuf, PathBuf),
+    Describe(Arc<SimilarImage>, Arc<SimilarImage>),
+}
+
+/// What the loader hands back to the main thread
+pub enum Loaded {
+    /// An image decoded at full size, as the frames of an animation or
+    /// the one frame of anything else
+    Image(PathBuf, Vec<RgbImage>),
+    /// A pair of images brought to the same dimensions to be compared,
+    /// and the heatmap of the difference between them
+    Aligned(PathBuf, PathBuf, RgbImage, RgbImage, RgbImage),
+    /// The details of a pair of images, which may have to be decompressed
+    /// from an archive to be read
+    Metadata(PathBuf, PathBuf, Metadata, Metadata),
+}
+
+/// Decodes images at full size on a worker thread, so that the window
+/// keeps responding while large images and videos load
+pub struct Loader {
+    requests: Sender<Request>,
+    /// Carries the decoded images back to the main thread while it lives
+    _channel: Channel<Loaded>,
+}
+
+impl Loader {
+    /// Start the worker.  `loaded` is called on the main thread with
+    /// everything done, along with the paths it was asked for by.
+    pub fn new<F>(loaded: F) -> Loader
+    where
+        F: FnMut(Loaded) + 'static,
+    {
+        let (channel, sender) = Channel::new(loaded);
+        let (requests, receiver) = channel::<Request>();
+        thread::spawn(move || {
+            let mut queue = Queue::default();
+            // Only the latest pair asked for is described and then aligned,
+            // before anything else
+            let mut describe = None;
+            let mut align = None;
+            loop {
+                let request = match receiver.try_recv() {
+                    Ok(request) => Some(request),
+                    Err(TryRecvError::Empty)
+                        if queue.is_empty() && align.is_none() && describe.is_none() =>
+                    {
+                        match receiver.recv() {
+                            Ok(request) => Some(request),
+                            Err(_) => break,
+                        }
+                    }
+                    Err(TryRecvError::Empty) => None,
+                    Err(TryRecvError::Disconnected) => break,
+                };
+                let done = match request {
+                    Some(Request::Load(paths)) => {
+                        queue.request(paths);
+                        continue;
+                    }
+                    Some(Request::Align(left, right)) => {
+                        align = Some((left, right));
+                        continue;
+                    }
+                    Some(Request::Describe(left, right)) => {
+                        describe = Some((left, right));
+                        continue;
+                    }
+                    None => {
+                        if let Some((left, right)) = describe.take() {
+                            Some(described(&left, &right))
+                        } else if let Some((left, right)) = align.take() {
+                            aligned(left, right)
+                        } else {
+                            queue.next().and_then(|path| {
+                                let frames = open_frames(&path)?;
+                                Some(Loaded::Image(path, frames))
+                            })
+                        }
+                    }
+                };
+                if let Some(done) = done {
+                    if sender.send(done).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+        Loader {
+            requests,
+            _channel: channel,
+        }
+    }
+
+    /// Decode the images at `paths` in order, instead of any still waiting
+    /// from earlier requests
+    pub fn load(&self, paths: Vec<PathBuf>) {
+        // The worker only stops once the loader is dropped
+        let _ = self.requests.send(Request::Load(paths));
+    }
+
+    /// Decode a pair and bring both images to the same dimensions,
+    /// instead of any pair asked for before
+    pub fn align(&self, left: PathBuf, right: PathBuf) {
+        let _ = self.requests.send(Request::Align(left, right));
+    }
+
+    /// Read the details of a pair, instead of any pair asked for before
+    pub fn describe(&self, left: Arc<SimilarImage>, right: Arc<SimilarImage>) {
+        let _ = self.requests.send(Request::Describe(left, right));
+    }
+}
+
+fn open(path: &Path) -> Option<DynamicImage> {
+    scanner::open_image(path)
+        .map_err(|e| warn!("Could not display {:?}: {}", path, e))
+        .ok()
+}
+
+fn open_frames(path: &Path) -> Option<Vec<RgbImage>> {
+    let frames = scanner::open_frames(path, ANIMATION_FRAMES)
+        .map_err(|e| warn!("Could not display {:?}: {}", path, e))
+        .ok()?;
+    Some(frames.iter().map(DynamicImage::to_rgb).collect())
+}
+
+fn aligned(left: PathBuf, right: PathBuf) -> Option<Loaded> {
+    let (left_image, right_image) = (open(&left)?, open(&right)?);
+    let (left_image, right_image) = scanner::align(&left_image, &right_image);
+    let heatmap = scanner::heatmap(&left_image, &right_image);
+    Some(Loaded::Aligned(
+        left,
+        right,
+        left_image,
+        right_image,
+        heatmap,
+    ))
+}
+
+fn described(left: &SimilarImage, right: &SimilarImage) -> Loaded {
+    let (left_metadata, right_metadata) = (Metadata::read(left), Metadata::read(right));
+    Loaded::Metadata(
+        left.path.clone(),
+        right.path.clone(),
+        left_metadata,
+        right_metadata,
+    )
+}
+
+/// The images the loader still has to decode
+#[derive(Default)]
+struct Queue {
+    wanted: VecDeque<PathBuf>,
+    /// Images decoded since the last request, which may have still
+    /// been on their way when it was made
+    sent: Vec<PathBuf>,
+}
+
+impl Queue {
+    fn is_empty(&self) -> bool {
+        self.wanted.is_empty()
+    }
+
+    /// Decode `paths` instead of whatever was wanted before
+    fn request(&mut self, paths: Vec<PathBuf>) {
+        let sent = &self.sent;
+        self.wanted = paths.into_iter().filter(|p| !sent.contains(p)).collect();
+        self.sent.clear();
+    }
+
+    /// The next image to decode, which counts as sent from now on
+    fn next(&mut self) -> Option<PathBuf> {
+        let path = self.wanted.pop_front()?;
+        self.sent.push(path.clone());
+        Some(path)
+    }
+}
+
+/// Images decoded by the loader, as their frames, up to `CACHE_BYTES` of
+/// them.  When there are too many, those no longer wanted go first, least
+/// recently used first, and then those wanted furthest ahead.
+pub struct Cache<T = Vec<Pixbuf>> {
+    /// Least recently used first, with the bytes each takes up
+    images: VecDeque<(PathBuf, T, usize)>,
+    bytes: usize,
+    limit: usize,
+    /// The images of the current pair and the next few, in that order
+    wanted: Vec<PathBuf>,
+}
+
+impl<T: Clone> Default for Cache<T> {
+    fn default() -> Cache<T> {
+        Cache::with_limit(CACHE_BYTES)
+    }
+}
+
+impl<T: Clone> Cache<T> {
+    fn with_limit(limit: usize) -> Cache<T> {
+        Cache {
+            images: VecDeque::new(),
+            bytes: 0,
+            limit,
+            wanted: Vec::new(),
+        }
+    }
+
+    /// The image decoded from `path`, if it is still cached
+    pub fn get(&mut self, path: &Path) -> Option<T> {
+        let index = self.images.iter().position(|(p, _, _)| p == path)?;
+        let entry = self.images.remove(index)?;
+        let image = entry.1.clone();
+        self.images.push_back(entry);
+        Some(image)
+    }
+
+    pub fn contains(&self, path: &Path) -> bool {
+        self.images.iter().any(|(p, _, _)| p == path)
+    }
+
+    /// Which images will be shown next, most urgent first, so that they
+    /// are kept in favour of the rest
+    pub fn want(&mut self, paths: Vec<PathBuf>) {
+        self.wanted = paths;
+    }
+
+    pub fn insert(&mut self, path: PathBuf, image: T, bytes: usize) {
+        if let Some(index) = self.images.iter().position(|(p, _, _)| *p == path) {
+            self.remove(index);
+        }
+        self.bytes += bytes;
+        self.images.push_back((path, image, bytes));
+        // Always keep one, however big
+        while self.bytes > self.limit && self.images.len() > 1 {
+            let wanted = &self.wanted;
+            let rank = |path: &PathBuf| wanted.iter().position(|p| p == path);
+            // The oldest of those not wanted, or the one wanted last
+            let (index, _) = self
+                .images
+                .iter()
+                .enumerate()
+                .rev()
+                .max_by_key(|(_, (path, _, _))| rank(path).unwrap_or(usize::MAX))
+                .expect("the cache is not empty");
+            self.remove(index);
+        }
+    }
+
+    fn remove(&mut self, index: usize) {
+        if let Some((_, _, bytes)) = self.images.remove(index) {
+            self.bytes -= bytes;
+        }
+    }
+}
+
+/// Bytes of pixel data in the frames of an image
+pub fn size(frames: &[Pixbuf]) -> usize {
+    frames
+        .iter()
+        .map(|pixbuf| pixbuf.get_rowstride() as usize * pixbuf.get_height() as usize)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(names: &[&str]) -> Vec<PathBuf> {
+        names.iter().map(PathBuf::from).collect()
+    }
+
+    #[test]
+    fn images_wanted_soonest_are_kept() {
+        let mut cache = Cache::with_limit(30);
+        cache.want(paths(&["1.jpg", "2.jpg", "3.jpg"]));
+        cache.insert(PathBuf::from("old.jpg"), 0, 10);
+        cache.insert(PathBuf::from("1.jpg"), 1, 10);
+        cache.insert(PathBuf::from("2.jpg"), 2, 10);
+        // Too much, so the image no longer wanted goes first
+        cache.insert(PathBuf::from("3.jpg"), 3, 10);
+        assert!(!cache.contains(Path::new("old.jpg")));
+        assert_eq!(cache.get(Path::new("1.jpg")), Some(1));
+
+        // and then the one wanted furthest ahead, even if it is the newest
+        cache.want(paths(&["2.jpg", "4.jpg", "3.jpg"]));
+        cache.insert(PathBuf::from("4.jpg"), 4, 10);
+        assert!(!cache.contains(Path::new("1.jpg")));
+        cache.insert(PathBuf::from("5.jpg"), 5, 10);
+        assert!(!cache.contains(Path::new("5.jpg")));
+        assert_eq!(cache.bytes, 30);
+    }
+
+    #[test]
+    fn requests_skip_images_already_sent() {
+        let mut queue = Queue::default();
+        queue.request(paths(&["a.jpg", "b.jpg", "c.jpg"]));
+        assert_eq!(queue.next(), Some(PathBuf::from("a.jpg")));
+        // a.jpg may not have arrived when this was asked for
+        queue.request(paths(&["a.jpg", "c.jpg", "d.jpg"]));
+        assert_eq!(queue.next(), Some(PathBuf::from("c.jpg")));
+        assert_eq!(queue.next(), Some(PathBuf::from("d.jpg")));
+        assert_eq!(queue.next(), None);
+        assert!(queue.is_empty());
+        // Once asked for again, images are decoded again
+        queue.request(paths(&["a.jpg"]));
+        assert_eq!(queue.next(), Some(PathBuf::from("a.jpg")));
+    }
+}
@@ -1,39 +1,38 @@
 use gtk::prelude::*;
 use gtk::{FileChooserAction, FileChooserDialog, Orientation::Vertical};
-use log::{debug, info};
+use log::{debug, info, warn};
 use relm::{connect, connect_stream, Channel, EventStream, Relm, Widget};
 use relm_attributes::widget;
 use relm_derive::Msg;
 
 mod comparewidget;
 mod configwidget;
+mod loader;
+mod pairlist;
 mod radiowidget;
+mod summarywidget;
 mod waitwidget;
 
 use self::comparewidget::{CompareWidget, Msg as CompareMsg};
 use self::configwidget::{ConfigWidget, Msg as ConfigMsg, Msg::*};
+use self::summarywidget::{Msg as SummaryMsg, SummaryWidget};
 use self::waitwidget::{Msg as WaitMsg, WaitWidget};
-use img_dedup::{self as scanner, Config, SimilarPair, StatusMsg};
+use img_dedup::{self as scanner, Config, IgnoreList, Review, SimilarPair, StatusMsg, Summary};
 use std::collections::BinaryHeap;
 use std::path::PathBuf;
 use std::sync::mpsc::channel;
 use std::thread;
 
-/// An unsafe container for my Binary Heap.
-///
-/// I want to create this off of the main thread
-/// At the same time, I want this to contain `Rc`'s, which are not `Send`
-/// So I'm unsafe-impl-ing `Send`.
-/// I promise the creator does not have any references.
-///
-/// I did test this
-
-pub struct ScannedFiles(BinaryHeap<SimilarPair>);
-unsafe impl Send for ScannedFiles {}
+/// How many of the files that could not be read are named in the window
+const FAILURES_SHOWN: usize = 5;
 
 pub struct Model {
     config: Config,
     stream: EventStream<Msg>,
+    /// Files left out of the last scan, and why
+    failures: Vec<(PathBuf, String)>,
+    /// Why the last thing tried did not work, shown above everything else
+    problem: String,
 }
 
 #[derive(Msg)]
@@ -42,7 +41,27 @@ pub enum Msg {
     SelectFolder,
     ChangeHashLen(u32),
     ChangeMethod(&'static str),
-    Done(ScannedFiles),
+    ToggleVideo(bool),
+    ToggleArchives(bool),
+    ChangeThreads(usize),
+    ChangeIoThreads(usize),
+    ToggleOrderedReads(bool),
+    ChangeProfile(String),
+    SaveProfile,
+    OpenSession,
+    SaveSessionAs,
+    Done(BinaryHeap<SimilarPair>),
+    /// The scan could not be finished, for the given reason
+    ScanFailed(String),
+    /// A file was left out of the scan, for the given reason
+    Failed(PathBuf, String),
+    Finished(Summary),
+    ShowCompare,
+    ConfirmDeletions,
+    /// Some of the chosen files have been dealt with, out of how many
+    Deleting(usize, usize),
+    Deleted(Vec<PathBuf>),
+    KeyPress(u32),
     Quit,
 }
 
@@ -50,7 +69,21 @@ pub enum Msg {
 impl Widget for Win {
     fn model(relm: &Relm<Self>, config: Config) -> Model {
         let stream = relm.stream().clone();
-        Model { config, stream }
+        Model {
+            config,
+            stream,
+            failures: Vec::new(),
+            problem: String::new(),
+        }
+    }
+
+    fn init_view(&mut self) {
+        // Carry on with the session given on the command line, if it was saved before
+        if let Some(path) = self.model.config.session.clone() {
+            if path.exists() {
+                self.resume(path);
+            }
+        }
     }
 
     fn update(&mut self, event: Msg) {
@@ -58,23 +91,104 @@ impl Widget for Win {
             Msg::Quit => gtk::main_quit(),
             Msg::SelectFolder => {
                 if let Some(directory) = self.select_directory() {
+                    // The folder may have its own config file
+                    match Config::for_directory(directory.clone()) {
+                        Ok(config) => {
+                            self.model.config = config;
+                            self.model.problem.clear();
+                        }
+                        Err(e) => {
+                            warn!("{}", e);
+                            self.model.config.directory = directory;
+                            self.model.problem = e.to_string();
+                        }
+                    }
                     self.config
-                        .emit(ConfigMsg::ChangeDirectory(directory.clone()));
-                    self.model.config.directory = directory;
+                        .emit(ConfigMsg::SetConfig(self.model.config.clone()));
                 }
             }
             Msg::ChangeHashLen(len) => self.model.config.hash_size = len,
             Msg::ChangeMethod(method) => self.model.config.method = method.parse().unwrap(),
+            Msg::ToggleVideo(video) => self.model.config.video = video,
+            Msg::ToggleArchives(archives) => self.model.config.archives = archives,
+            Msg::ChangeThreads(threads) => self.model.config.threads = threads,
+            Msg::ChangeIoThreads(threads) => self.model.config.io_threads = threads,
+            Msg::ToggleOrderedReads(ordered) => self.model.config.ordered_reads = ordered,
+            Msg::ChangeProfile(name) => {
+                self.model.config.profile = Some(name).filter(|n| !n.is_empty());
+            }
+            Msg::SaveProfile => self.save_profile(),
+            Msg::OpenSession => {
+                if let Some(path) = self.choose_file("Open a session", FileChooserAction::Open) {
+                    self.resume(path);
+                }
+            }
+            Msg::SaveSessionAs => {
+                if let Some(path) = self.choose_file("Save the session", FileChooserAction::Save) {
+                    self.compare.emit(CompareMsg::SetSession(path));
+                }
+            }
             Msg::Deduplicate => {
                 info!("{:#?}", self.model.config);
+                self.model.failures.clear();
+                if let Err(e) = self.model.config.validate() {
+                    warn!("{}", e);
+                    self.model.problem = e.to_string();
+                    return;
+                }
+                self.model.problem.clear();
+                self.wait.emit(WaitMsg::Reset);
                 self.stack.set_visible_child(self.wait.widget());
                 self.run_scanner();
             }
-            Msg::Done(ScannedFiles(files)) => {
+            Msg::Done(files) => {
                 debug!("{:#?}", files);
                 self.compare.emit(CompareMsg::SetFiles(files));
+                if let Some(path) = &self.model.config.session {
+                    self.compare.emit(CompareMsg::SetSession(path.clone()));
+                }
                 self.stack.set_visible_child(self.compare.widget());
             }
+            Msg::ScanFailed(reason) => {
+                self.model.problem = format!("The scan failed: {}", reason);
+                self.stack.set_visible_child(self.config.widget());
+            }
+            Msg::Failed(path, reason) => {
+                self.model.failures.push((path, reason));
+                self.model.problem = failures_text(&self.model.failures);
+            }
+            Msg::Finished(summary) => {
+                self.summary.emit(SummaryMsg::Show(summary));
+                self.stack.set_visible_child(self.summary.widget());
+            }
+            Msg::ShowCompare => self.stack.set_visible_child(self.compare.widget()),
+            Msg::Deleting(done, total) => {
+                if done == 0 {
+                    self.stack.set_visible_child(self.wait.widget());
+                }
+                self.wait.emit(WaitMsg::Deleting(done, total));
+            }
+            Msg::Deleted(paths) => {
+                self.compare.emit(CompareMsg::Forget(paths));
+                self.stack.set_visible_child(self.summary.widget());
+            }
+            Msg::ConfirmDeletions => {
+                if self.confirm("Delete the chosen files? This cannot be undone.") {
+                    self.summary.emit(SummaryMsg::Confirmed);
+                }
+            }
+            Msg::KeyPress(keyval) => {
+                // Shortcuts only apply while comparing, so typing elsewhere is unaffected
+                let comparing = self.stack.get_visible_child().as_ref()
+                    == Some(self.compare.widget().upcast_ref::<gtk::Widget>());
+                let typing = self
+                    .window
+                    .get_focus()
+                    .map_or(false, |w| w.is::<gtk::Entry>());
+                if comparing && !typing {
+                    self.compare.emit(CompareMsg::KeyPress(keyval));
+                }
+            }
         }
     }
 
@@ -84,33 +198,57 @@ impl Widget for Win {
             title: "Image Deduplicator",
             gtk::Box {
                 orientation: Vertical,
+                gtk::Label {
+                    text: &self.model.problem,
+                    visible: !self.model.problem.is_empty(),
+                    selectable: true,
+                },
                 #[name="stack"]
                 gtk::Stack {
                     #[name="config"]
-                    // I would love to be able to pass in a reference to the config object itself
-                    ConfigWidget(self.model.config.directory.clone(), self.model.config.hash_size) {
+                    ConfigWidget(self.model.config.clone()) {
                         OpenFileChooser => Msg::SelectFolder,
                         Deduplicate => Msg::Deduplicate,
                         ChangeHashLen(len) => Msg::ChangeHashLen(len),
                         ChangeMethod(m) => Msg::ChangeMethod(m),
+                        ToggleVideo(v) => Msg::ToggleVideo(v),
+                        ToggleArchives(a) => Msg::ToggleArchives(a),
+                        ChangeThreads(t) => Msg::ChangeThreads(t),
+                        ChangeIoThreads(t) => Msg::ChangeIoThreads(t),
+                        ToggleOrderedReads(o) => Msg::ToggleOrderedReads(o),
+                        ChangeProfile(ref p) => Msg::ChangeProfile(p.clone()),
+                        SaveProfile => Msg::SaveProfile,
+                        OpenSession => Msg::OpenSession,
                     },
                     #[name="wait"]
                     WaitWidget() { },
                     #[name="compare"]
-                    CompareWidget(BinaryHeap::<SimilarPair>::new()) { },
+                    CompareWidget(BinaryHeap::<SimilarPair>::new()) {
+                        CompareMsg::SaveSessionAs => Msg::SaveSessionAs,
+                        CompareMsg::Finished(ref s) => Msg::Finished(s.clone()),
+                    },
+                    #[name="summary"]
+                    SummaryWidget() {
+                        SummaryMsg::Back => Msg::ShowCompare,
+                        SummaryMsg::Apply => Msg::ConfirmDeletions,
+                        SummaryMsg::Deleting(done, total) => Msg::Deleting(done, total),
+                        SummaryMsg::Deleted(ref paths) => Msg::Deleted(paths.clone()),
+                    },
                 }
             },
+            key_press_event(_, event) => (Msg::KeyPress(event.get_keyval()), Inhibit(false)),
             delete_event(_, _) => (Msg::Quit, Inhibit(false)),
         }
     }
 }
 impl Win {
     fn select_directory(&mut self) -> Option<PathBuf> {
-        let dialog = FileChooserDialog::new(
-            Some("Select a folder"),
-            Some(&self.window),
-            FileChooserAction::SelectFolder,
-        );
+        self.choose_file("Select a folder", FileChooserAction::SelectFolder)
+    }
+
+    fn choose_file(&self, title: &str, action: FileChooserAction) -> Option<PathBuf> {
+        let dialog = FileChooserDialog::new(Some(title), Some(&self.window), action);
+        dialog.set_do_overwrite_confirmation(true);
         dialog.add_button("Ok", gtk::ResponseType::Ok.into());
         dialog.add_button("Cancel", gtk::ResponseType::Cancel.into());
         let response_ok: i32 = gtk::ResponseType::Ok.into();
@@ -123,32 +261,111 @@ impl Win {
         None
     }
 
+    /// Ask the user to go ahead with something, true if they did
+    fn confirm(&self, question: &str) -> bool {
+        let dialog = gtk::MessageDialog::new(
+            Some(&self.window),
+            gtk::DialogFlags::MODAL,
+            gtk::MessageType::Warning,
+            gtk::ButtonsType::OkCancel,
+            question,
+        );
+        let response_ok: i32 = gtk::ResponseType::Ok.into();
+        let confirmed = dialog.run() == response_ok;
+        dialog.destroy();
+        confirmed
+    }
+
+    fn resume(&mut self, path: PathBuf) {
+        match Review::open(&path) {
+            Ok(review) => {
+                info!("Resuming the session in {:?}", path);
+                self.model.problem.clear();
+                self.compare.emit(CompareMsg::Resume(review));
+                self.compare.emit(CompareMsg::SetSession(path));
+                self.stack.set_visible_child(self.compare.widget());
+            }
+            Err(e) => {
+                warn!("{}", e);
+                self.model.problem = format!("Could not resume the session: {}", e);
+            }
+        }
+    }
+
+    fn save_profile(&mut self) {
+        let config = &self.model.config;
+        self.model.problem = match &config.profile {
+            Some(name) => match config.save_profile(name) {
+                Ok(path) => {
+                    info!("Saved profile {:?} to {:?}", name, path);
+                    String::new()
+                }
+                Err(e) => format!("Could not save profile {:?}: {}", name, e),
+            },
+            None => "Enter a name to save the profile under".to_string(),
+        };
+        if !self.model.problem.is_empty() {
+            warn!("{}", self.model.problem);
+        }
+    }
+
     fn run_scanner(&self) {
         let (sender, receiver) = channel::<StatusMsg>();
-        let directory = self.model.config.directory.clone();
-        let method = self.model.config.method.clone();
-        let hash_size = self.model.config.hash_size;
+        let mut config = self.model.config.clone();
+        // Browsing the results shows these until the images are decoded
+        config.thumbnails = true;
+        let ignored = load_ignored();
 
         let stream = self.model.stream.clone();
         // Spawn a thread to scan the files
-        thread::spawn(move || {
-            let files =
-                ScannedFiles(scanner::scan_files(&directory, method, hash_size, sender).unwrap());
-
-            stream.emit(Msg::Done(files));
-            info!("Done");
+        thread::spawn(move || match scanner::scan(&config, &ignored, sender) {
+            Ok(files) => {
+                stream.emit(Msg::Done(files));
+                info!("Done");
+            }
+            Err(e) => {
+                warn!("{}", e);
+                stream.emit(Msg::ScanFailed(e.to_string()));
+            }
         });
         let wait_stream = self.wait.stream().clone();
+        let stream = self.model.stream.clone();
         // Relm's channels wake up the thread
-        let (_channel, relm_sender) = Channel::new(move |msg| match msg {
-            StatusMsg::Total(i) => wait_stream.emit(WaitMsg::UpdateTotal(i)),
-            StatusMsg::ImageProcessed => wait_stream.emit(WaitMsg::UpdateProcessed),
+        let (_channel, relm_sender) = Channel::new(move |msg| {
+            if let StatusMsg::Failed { path, reason } = &msg {
+                stream.emit(Msg::Failed(path.clone(), reason.clone()));
+            }
+            wait_stream.emit(WaitMsg::Status(msg))
         });
         // Forward mpsc messages to relm messages
         thread::spawn(move || receiver.iter().for_each(|r| relm_sender.send(r).unwrap()));
     }
 }
 
+/// Name the first few files left out of a scan, and why
+fn failures_text(failures: &[(PathBuf, String)]) -> String {
+    let mut text = format!(
+        "Could not read {} files, which were left out:",
+        failures.len()
+    );
+    for (path, reason) in failures.iter().take(FAILURES_SHOWN) {
+        text.push_str(&format!("\n{}: {}", path.display(), reason));
+    }
+    if failures.len() > FAILURES_SHOWN {
+        text.push_str(&format!("\nand {} more", failures.len() - FAILURES_SHOWN));
+    }
+    text
+}
+
+/// The pairs the user said are not duplicates.  A broken list only warns,
+/// as it should not stop the user from scanning or reviewing.
+fn load_ignored() -> IgnoreList {
+    IgnoreList::load().unwrap_or_else(|e| {
+        warn!("{}", e);
+        IgnoreList::default()
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -156,16 +373,16 @@ mod tests {
     use std::sync::mpsc::channel;
 
     #[test]
-    fn scanned_files_is_safe() {
+    fn scans_on_another_thread() {
         let (sender, _receiver) = channel::<StatusMsg>();
-        let directory = PathBuf::from("./test");
-        let config = Config::default();
-        let files = ScannedFiles(
-            scanner::scan_files(&directory, config.method, config.hash_size, sender).unwrap(),
-        );
+        let mut config = Config::default();
+        config.directory = PathBuf::from("./test");
+        let ignored = IgnoreList::default();
+        let files = thread::spawn(move || scanner::scan(&config, &ignored, sender).unwrap())
+            .join()
+            .unwrap();
         files
-            .0
             .into_iter()
-            .for_each(|x| println!("{:?} > {:?}", x.left.borrow_mut(), x.right.borrow_mut()));
+            .for_each(|x| println!("{:?} > {:?}", x.left, x.right));
     }
 }
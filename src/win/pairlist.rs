@@ -0,0 +1,219 @@
+use super::radiowidget::{Msg::Clicked, RadioWidget};
+use gtk::prelude::*;
+use gtk::Orientation::Vertical;
+use img_dedup::SortOrder;
+use relm::{connect, connect_stream};
+use relm::{Relm, Widget};
+use relm_attributes::widget;
+use relm_derive::Msg;
+use std::collections::HashMap;
+
+use self::Msg::*;
+
+/// How long the filters have to be left alone before the list is
+/// filtered again, in milliseconds
+const FILTER_DELAY: u32 = 300;
+
+pub struct Model {
+    relm: Relm<PairList>,
+    /// The label of each row
+    store: gtk::ListStore,
+    /// Index into the review of the pair on each row
+    indices: Vec<usize>,
+    /// The row of each pair listed
+    rows: HashMap<usize, i32>,
+    /// Counts changes to the filters, so only the last is passed on
+    edits: u32,
+    /// Filters changed but not yet passed on
+    folder: Option<String>,
+    min_similarity: Option<usize>,
+    min_size: Option<u64>,
+}
+
+#[derive(Msg)]
+pub enum Msg {
+    /// Replace the rows with these pairs, given by index and label
+    SetRows(Vec<(usize, String)>),
+    /// Change the label of the row for a pair, if it is listed
+    UpdateRow(usize, String),
+    /// Highlight the row for a pair, if it is listed
+    Select(usize),
+    RowActivated(i32),
+    EditFolder(String),
+    EditMinSimilarity(usize),
+    EditMinSize(u64),
+    /// The filters have not changed since the given edit
+    Settled(u32),
+    /// Sent when a row is picked, with the index of its pair
+    GoTo(usize),
+    ChangeFolder(String),
+    ChangeMinSimilarity(usize),
+    /// In bytes
+    ChangeMinSize(u64),
+    ChangeOrder(&'static str),
+}
+
+#[widget]
+impl Widget for PairList {
+    fn model(relm: &Relm<Self>, _: ()) -> Model {
+        Model {
+            relm: relm.clone(),
+            store: gtk::ListStore::new(&[gtk::Type::String]),
+            indices: Vec::new(),
+            rows: HashMap::new(),
+            edits: 0,
+            folder: None,
+            min_similarity: None,
+            min_size: None,
+        }
+    }
+
+    fn init_view(&mut self) {
+        // Every row is one line, so their heights need not be measured
+        let column = gtk::TreeViewColumn::new();
+        let cell = gtk::CellRendererText::new();
+        column.pack_start(&cell, true);
+        column.add_attribute(&cell, "text", 0);
+        column.set_sizing(gtk::TreeViewColumnSizing::Fixed);
+        self.tree.append_column(&column);
+        self.tree.set_fixed_height_mode(true);
+    }
+
+    fn update(&mut self, event: Msg) {
+        match event {
+            SetRows(rows) => {
+                // Filling a store that is not shown saves redrawing on every row
+                let store = gtk::ListStore::new(&[gtk::Type::String]);
+                for (_, text) in &rows {
+                    store.insert_with_values(None, &[0], &[text]);
+                }
+                self.tree.set_model(Some(&store));
+                self.model.store = store;
+                self.model.indices = rows.into_iter().map(|(index, _)| index).collect();
+                self.model.rows = self
+                    .model
+                    .indices
+                    .iter()
+                    .enumerate()
+                    .map(|(row, &index)| (index, row as i32))
+                    .collect();
+            }
+            UpdateRow(index, text) => {
+                if let Some(iter) = self.row(index) {
+                    self.model.store.set_value(&iter, 0, &text.to_value());
+                }
+            }
+            Select(index) => {
+                let path = self
+                    .row(index)
+                    .and_then(|iter| self.model.store.get_path(&iter));
+                match path {
+                    Some(path) => {
+                        // Scroll to the row without taking the focus from the filters
+                        self.tree.get_selection().select_path(&path);
+                        self.tree.scroll_to_cell(
+                            &path,
+                            None::<&gtk::TreeViewColumn>,
+                            false,
+                            0.0,
+                            0.0,
+                        );
+                    }
+                    None => self.tree.get_selection().unselect_all(),
+                }
+            }
+            RowActivated(row) => {
+                if let Some(&index) = self.model.indices.get(row as usize) {
+                    self.model.relm.stream().emit(GoTo(index));
+                }
+            }
+            EditFolder(folder) => {
+                self.model.folder = Some(folder);
+                self.edited();
+            }
+            EditMinSimilarity(similarity) => {
+                self.model.min_similarity = Some(similarity);
+                self.edited();
+            }
+            EditMinSize(size) => {
+                self.model.min_size = Some(size);
+                self.edited();
+            }
+            Settled(edit) => {
+                if edit == self.model.edits {
+                    let stream = self.model.relm.stream();
+                    if let Some(folder) = self.model.folder.take() {
+                        stream.emit(ChangeFolder(folder));
+                    }
+                    if let Some(similarity) = self.model.min_similarity.take() {
+                        stream.emit(ChangeMinSimilarity(similarity));
+                    }
+                    if let Some(size) = self.model.min_size.take() {
+                        stream.emit(ChangeMinSize(size));
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    view! {
+        gtk::Box {
+            orientation: Vertical,
+            gtk::Label {
+                text: "Sort by",
+            },
+            RadioWidget(SortOrder::available_orders()) {
+                Clicked(o) => ChangeOrder(o)
+            },
+            gtk::Label {
+                text: "Folder contains",
+            },
+            gtk::Entry {
+                changed(e) => EditFolder(e.get_text().unwrap_or_default()),
+            },
+            gtk::Label {
+                text: "Minimum similarity",
+            },
+            gtk::SpinButton {
+                adjustment: &gtk::Adjustment::new(0., 0., 4096., 1., 10., 0.,),
+                value_changed(w) => EditMinSimilarity(w.get_value_as_int() as usize),
+            },
+            gtk::Label {
+                text: "Minimum file size (KiB)",
+            },
+            gtk::SpinButton {
+                adjustment: &gtk::Adjustment::new(0., 0., 1_048_576., 16., 1024., 0.,),
+                value_changed(w) => EditMinSize(w.get_value_as_int() as u64 * 1024),
+            },
+            gtk::ScrolledWindow {
+                vexpand: true,
+                min_content_width: 250,
+                #[name="tree"]
+                gtk::TreeView {
+                    headers_visible: false,
+                    enable_search: false,
+                    row_activated(_, path, _) =>
+                        RowActivated(path.get_indices().first().cloned().unwrap_or(-1)),
+                },
+            },
+        },
+    }
+}
+
+impl PairList {
+    fn row(&self, index: usize) -> Option<gtk::TreeIter> {
+        let row = *self.model.rows.get(&index)?;
+        self.model.store.iter_nth_child(None, row)
+    }
+
+    /// Pass the filters on once they are left alone for `FILTER_DELAY`,
+    /// as filtering many pairs on every key press would stall typing
+    fn edited(&mut self) {
+        self.model.edits = self.model.edits.wrapping_add(1);
+        let edit = self.model.edits;
+        relm::timeout(self.model.relm.stream(), FILTER_DELAY, move || {
+            Settled(edit)
+        });
+    }
+}
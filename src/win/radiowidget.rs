@@ -8,7 +8,7 @@ use self::Msg::*;
 
 pub struct Widgets {
     root: gtk::Box,
-    _buttons: Vec<gtk::RadioButton>,
+    buttons: Vec<(&'static str, gtk::RadioButton)>,
 }
 
 pub struct RadioWidget {
@@ -22,6 +22,7 @@ pub struct Model {
 #[derive(Msg)]
 pub enum Msg {
     Clicked(&'static str),
+    Select(&'static str),
 }
 
 impl Update for RadioWidget {
@@ -39,6 +40,12 @@ impl Update for RadioWidget {
     fn update(&mut self, event: Msg) {
         match event {
             Clicked(_) => (),
+            // Activating a radio button clicks it, which reports the change
+            Select(text) => {
+                if let Some((_, button)) = self.widgets.buttons.iter().find(|b| b.0 == text) {
+                    button.set_active(true);
+                }
+            }
         }
     }
 }
@@ -54,17 +61,19 @@ impl Widget for RadioWidget {
     fn view(relm: &Relm<Self>, model: Self::Model) -> Self {
         let vbox = gtk::Box::new(Vertical, 0);
 
-        let mut buttons: Vec<gtk::RadioButton> = Vec::new();
+        let mut buttons: Vec<(&'static str, gtk::RadioButton)> = Vec::new();
 
         for (text, desc) in model.list.into_iter() {
             let radio_button = match buttons.get(0) {
-                Some(old_button) => gtk::RadioButton::new_with_label_from_widget(old_button, text),
+                Some((_, old_button)) => {
+                    gtk::RadioButton::new_with_label_from_widget(old_button, text)
+                }
                 None => gtk::RadioButton::new_with_label(text),
             };
             radio_button.set_tooltip_text(Some(desc));
             vbox.add(&radio_button);
             connect!(relm, radio_button, connect_clicked(_), Msg::Clicked(text));
-            buttons.push(radio_button);
+            buttons.push((text, radio_button));
         }
 
         vbox.show_all();
@@ -72,7 +81,7 @@ impl Widget for RadioWidget {
         RadioWidget {
             widgets: Widgets {
                 root: vbox,
-                _buttons: buttons,
+                buttons,
             },
         }
     }
@@ -0,0 +1,170 @@
+use self::Msg::*;
+use gtk::prelude::*;
+use gtk::Orientation::{Horizontal, Vertical};
+use img_dedup::{ScanError, Summary};
+use log::{info, warn};
+use relm::{Channel, Relm, Widget};
+use relm_attributes::widget;
+use relm_derive::Msg;
+use std::path::PathBuf;
+use std::thread;
+
+pub struct Model {
+    relm: Relm<SummaryWidget>,
+    summary: Summary,
+    totals: String,
+    /// Every file to be deleted and every file passed over, one per line
+    files: String,
+    /// What happened when the deletions were carried out
+    result: String,
+    can_apply: bool,
+    /// Files deleted so far, and why others were not
+    deleted: Vec<PathBuf>,
+    not_deleted: Vec<String>,
+    /// Carries the results back from the thread deleting the files
+    channel: Option<Channel<Result<PathBuf, ScanError>>>,
+}
+
+#[derive(Msg)]
+pub enum Msg {
+    Show(Summary),
+    /// Sent to go back to reviewing
+    Back,
+    /// Sent to ask for the deletions to be confirmed
+    Apply,
+    /// The deletions were confirmed, so carry them out
+    Confirmed,
+    /// A file was deleted, or could not be
+    Applied(Result<PathBuf, ScanError>),
+    /// Sent with how many of the files have been dealt with so far,
+    /// out of how many there are
+    Deleting(usize, usize),
+    /// Sent with the files that were deleted, once all have been dealt with
+    Deleted(Vec<PathBuf>),
+}
+
+#[widget]
+impl Widget for SummaryWidget {
+    fn model(relm: &Relm<Self>, _: ()) -> Model {
+        Model {
+            relm: relm.clone(),
+            summary: Summary::default(),
+            totals: String::new(),
+            files: String::new(),
+            result: String::new(),
+            can_apply: false,
+            deleted: Vec::new(),
+            not_deleted: Vec::new(),
+            channel: None,
+        }
+    }
+
+    fn update(&mut self, event: Msg) {
+        match event {
+            Show(summary) => {
+                self.model.totals = summary.to_string();
+                let deletions = summary.deletions.iter().map(|(delete, keep)| {
+                    format!(
+                        "Delete {} (keeping {})",
+                        delete.path.display(),
+                        keep.path.display()
+                    )
+                });
+                let kept = summary.kept.iter().map(|path| {
+                    format!(
+                        "Keep {} (the other copy is going, or it is in an archive)",
+                        path.display()
+                    )
+                });
+                self.model.files = deletions.chain(kept).collect::<Vec<_>>().join("\n");
+                self.model.result = String::new();
+                self.model.can_apply = !summary.deletions.is_empty();
+                self.model.summary = summary;
+            }
+            // Checking the files reads them in full, so that is done on
+            // another thread to keep the window responding
+            Confirmed => {
+                self.model.can_apply = false;
+                self.model.deleted.clear();
+                self.model.not_deleted.clear();
+                let stream = self.model.relm.stream().clone();
+                let (channel, sender) = Channel::new(move |result| stream.emit(Applied(result)));
+                let summary = self.model.summary.clone();
+                thread::spawn(move || {
+                    summary.apply_each(|result| {
+                        // Only fails once the window has gone
+                        let _ = sender.send(result);
+                    })
+                });
+                self.model.channel = Some(channel);
+                let total = self.model.summary.deletions.len();
+                self.model.relm.stream().emit(Deleting(0, total));
+            }
+            Applied(result) => {
+                match result {
+                    Ok(path) => self.model.deleted.push(path),
+                    Err(e) => {
+                        warn!("{}", e);
+                        self.model.not_deleted.push(format!("Not deleted: {}", e));
+                    }
+                }
+                let done = self.model.deleted.len() + self.model.not_deleted.len();
+                let total = self.model.summary.deletions.len();
+                self.model.relm.stream().emit(Deleting(done, total));
+                if done == total {
+                    self.finish_deleting();
+                }
+            }
+            Back | Apply | Deleting(..) | Deleted(_) => (),
+        }
+    }
+
+    view! {
+        gtk::Box {
+            orientation: Vertical,
+            gtk::Label {
+                text: "Review Summary",
+            },
+            gtk::Label {
+                text: &self.model.totals,
+            },
+            gtk::ScrolledWindow {
+                vexpand: true,
+                min_content_height: 300,
+                gtk::Label {
+                    text: &self.model.files,
+                    selectable: true,
+                    halign: gtk::Align::Start,
+                    valign: gtk::Align::Start,
+                },
+            },
+            gtk::Label {
+                text: &self.model.result,
+            },
+            gtk::Box {
+                orientation: Horizontal,
+                gtk::Button {
+                    clicked => Back,
+                    label: "Back to Review",
+                },
+                gtk::Button {
+                    clicked => Apply,
+                    label: "Delete Files",
+                    sensitive: self.model.can_apply,
+                },
+            },
+        },
+    }
+}
+
+impl SummaryWidget {
+    fn finish_deleting(&mut self) {
+        let deleted = std::mem::replace(&mut self.model.deleted, Vec::new());
+        info!("Deleted {} files", deleted.len());
+        let mut lines = vec![format!("Deleted {} files.", deleted.len())];
+        lines.append(&mut self.model.not_deleted);
+        self.model.result = lines.join("\n");
+        self.model.channel = None;
+        self.model.relm.stream().emit(Deleted(deleted));
+    }
+}
@@ -1,50 +1,66 @@
 use self::Msg::*;
 use gtk::prelude::*;
 use gtk::Orientation::Vertical;
+use img_dedup::{Phase, Progress, StatusMsg};
 use relm::{Relm, Widget};
 use relm_attributes::widget;
 use relm_derive::Msg;
 
 pub struct Model {
-    total: usize,
-    processed: usize,
+    progress: Progress,
     text: String,
+    current: String,
     fraction: f64,
+    comparison_fraction: f64,
+    /// Whether a scan is being shown, rather than files being deleted
+    scanning: bool,
 }
 
 #[derive(Msg)]
 pub enum Msg {
-    UpdateTotal(usize),
-    UpdateProcessed,
+    /// Start counting again, for a new scan
+    Reset,
+    Status(StatusMsg),
+    /// Show how many of the chosen files have been deleted, out of how many
+    Deleting(usize, usize),
 }
 
 #[widget]
 impl Widget for WaitWidget {
     fn model(_relm: &Relm<Self>, _: ()) -> Model {
+        let progress = Progress::new();
         Model {
-            processed: 0,
-            total: 0,
+            text: progress.to_string(),
+            current: String::new(),
             fraction: 0.0,
-            text: String::from("Searching for image files..."),
+            comparison_fraction: 0.0,
+            scanning: true,
+            progress,
         }
     }
 
     fn update(&mut self, event: Msg) {
         match event {
-            UpdateTotal(i) => self.model.total = i,
-            UpdateProcessed => self.model.processed += 1,
+            // Rates are measured from when the progress was made
+            Reset => self.model.progress = Progress::new(),
+            Status(msg) => self.model.progress.update(msg),
+            Deleting(done, total) => {
+                self.model.scanning = false;
+                self.model.text = format!("Deleted {} of {} files", done, total);
+                self.model.fraction = done as f64 / total.max(1) as f64;
+                self.model.current = String::new();
+                return;
+            }
+        };
+        self.model.scanning = true;
+        let progress = &self.model.progress;
+        self.model.text = progress.to_string();
+        self.model.fraction = progress.fraction();
+        self.model.comparison_fraction = progress.comparison_fraction();
+        self.model.current = match &progress.current {
+            Some(path) if progress.phase <= Phase::Hashing => path.display().to_string(),
+            _ => String::new(),
         };
-        if self.model.total > 0 && self.model.total == self.model.processed {
-            self.model.text = format!("Calculating differences for {} hashes", self.model.total);
-            self.model.fraction = 1.0;
-        } else {
-            self.model.fraction = self.model.processed as f64 / self.model.total as f64;
-            // TODO: Estimate time left?
-            self.model.text = format!(
-                "Processing files {} / {}",
-                self.model.processed, self.model.total
-            );
-        }
     }
 
     view! {
@@ -57,6 +73,14 @@ impl Widget for WaitWidget {
                 fraction: self.model.fraction,
                 show_text: true,
             },
+            gtk::Label {
+                text: &self.model.current,
+            },
+            gtk::ProgressBar {
+                fraction: self.model.comparison_fraction,
+                show_text: true,
+                visible: self.model.scanning,
+            },
         },
     }
 }